@@ -0,0 +1,172 @@
+//! Automatically turns group messages into spoilers.
+//!
+//! Messages that start with one of the spoiler hashtags or match one of the [filters] of a group
+//! are replaced by a spoiler the same way `/spoiler` does it.
+//!
+//! [filters]: crate::state::filter::Filter
+use std::sync::Arc;
+
+use teloxide::{
+    prelude::*,
+    types::{ParseMode, User},
+    utils::html,
+};
+
+use crate::{
+    bot::command::spoiler::{spoil_message, Arguments},
+    state::{spoiler::Content, State},
+    strings::bot_replies::{hashtag_tip, FILTER_REASON},
+};
+
+/// Handle text, animation, document, photo and video messages sent to a group.
+///
+/// Replaces the message by a spoiler if it starts with a spoiler hashtag of the group or its text
+/// or caption matches a filter of the group. Nothing happens unless the bot may delete messages
+/// in the group.
+pub(crate) async fn auto_spoiler(
+    bot: Bot,
+    message: Message,
+    state: Arc<State>,
+) -> ResponseResult<()> {
+    let content = match Content::from_message(&message) {
+        Some(
+            content @ (Content::Text(_)
+            | Content::Animation(..)
+            | Content::Document(..)
+            | Content::Photo(..)
+            | Content::Video(..)),
+        ) => content,
+        _ => return Ok(()),
+    };
+    let chat_id = message.chat.id;
+    let text = message.text().or_else(|| message.caption()).unwrap_or("");
+
+    let hashtag = state.leading_hashtag(&chat_id, text);
+    let arguments = match &hashtag {
+        Some(hashtag) => Arguments {
+            title: Some(hashtag.clone()),
+            ..Default::default()
+        },
+        None if state.matches_filter(&chat_id, text) => Arguments {
+            reason: Some(FILTER_REASON.to_string()),
+            ..Default::default()
+        },
+        None => return Ok(()),
+    };
+
+    // without the permission, the spoiler would be posted next to the message it should replace
+    match bot.get_chat_member(chat_id, state.bot_id).await {
+        Ok(member) if member.kind.can_delete_messages() => {}
+        Ok(_) => return Ok(()),
+        Err(e) => {
+            dbg!(e);
+            return Ok(());
+        }
+    }
+
+    let author = message
+        .from
+        .as_ref()
+        .map_or_else(String::new, |user| user.first_name.clone());
+
+    let bot_username = state.bot_username.clone();
+    let spoiled = spoil_message(
+        &bot, state, chat_id, message.id, &author, content, arguments,
+    )
+    .await;
+
+    if let (true, Some(hashtag), Some(author)) = (spoiled, hashtag, &message.from) {
+        send_hashtag_tip(&bot, chat_id, author, &hashtag, &bot_username).await;
+    }
+    Ok(())
+}
+
+/// Tells the author of a message that got spoiled via hashtag how to create spoilers themselves.
+///
+/// The author is mentioned, so they get notified even though their message is gone.
+async fn send_hashtag_tip(
+    bot: &Bot,
+    chat_id: ChatId,
+    author: &User,
+    hashtag: &str,
+    bot_username: &str,
+) {
+    let mention = html::user_mention(author.id, &author.first_name);
+    if let Err(e) = bot
+        .send_message(chat_id, hashtag_tip(&mention, hashtag, bot_username))
+        .parse_mode(ParseMode::Html)
+        .await
+    {
+        dbg!(e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        bot::{
+            mock_api::{self, MockApi},
+            recorded,
+        },
+        config::Config,
+    };
+
+    /// Sends a message starting with a spoiler hashtag to a group where the bot may delete
+    /// messages or not.
+    async fn send_hashtag(api: &MockApi, can_delete_messages: bool) -> Arc<State> {
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let message = recorded::group_message(json!({
+            "text": "#spoiler Snape kills Dumbledore",
+            "entities": [{ "offset": 0, "length": 8, "type": "hashtag" }],
+        }));
+        state.add_hashtag(message.chat.id, "#spoiler");
+        api.respond(
+            "getChatMember",
+            mock_api::ok(recorded::administrator(
+                recorded::bot_user(),
+                can_delete_messages,
+            )),
+        );
+
+        auto_spoiler(api.bot(), message, state.clone())
+            .await
+            .unwrap();
+        state
+    }
+
+    #[tokio::test]
+    async fn hashtags_turn_messages_into_spoilers() {
+        let api = MockApi::start().await;
+
+        let state = send_hashtag(&api, true).await;
+
+        assert_eq!(api.call("getChatMember")["user_id"], 1);
+        assert_eq!(api.call("deleteMessage")["message_id"], 1365);
+        let sent = api.calls("sendMessage");
+        assert_eq!(sent.len(), 2);
+        let spoiler = sent[0]["text"].as_str().unwrap();
+        assert!(spoiler.contains("<code>#spoiler</code>"), "{}", spoiler);
+        assert!(sent[0]["reply_markup"]["inline_keyboard"][0][0]["callback_data"].is_string());
+        let mention = r#"<a href="tg://user?id=10000">Lara</a>"#;
+        assert_eq!(
+            sent[1]["text"],
+            hashtag_tip(mention, "#spoiler", "spoilerowobot")
+        );
+        assert_eq!(sent[1]["parse_mode"], "HTML");
+        assert_eq!(state.spoiler_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn nothing_happens_without_permission_to_delete_messages() {
+        let api = MockApi::start().await;
+
+        let state = send_hashtag(&api, false).await;
+
+        assert_eq!(api.methods(), ["getchatmember"]);
+        assert_eq!(state.spoiler_count(), 0);
+    }
+}
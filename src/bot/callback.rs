@@ -3,90 +3,600 @@
 //! [callbacks]: https://core.telegram.org/bots/2-0-intro#callback-buttons
 use std::sync::Arc;
 
-use tbot::contexts::{methods::Callback, DataCallback};
+use teloxide::prelude::*;
+use url::Url;
 
-use crate::strings::bot_replies::{SPOILER_NOT_FOUND, TAP_AGAIN_TO_SHOW_SPOILER};
-use crate::strings::{INLINE_QUERY_SEPARATOR, MAJOR_SPOILER_IDENTIFIER};
-use crate::{state::spoiler::Content, util::start_url, State};
+use crate::strings::bot_replies::{
+    tap_more_times, BLURRED_COPY_SENT, CONFIRM_TO_SHOW_SPOILER, OUTDATED_BUTTON,
+    SHOW_SPOILER_FIRST, SLOW_DOWN, SPOILER_NOT_FOUND, TAP_AGAIN_TO_SHOW_SPOILER, UNKNOWN_BUTTON,
+};
+use crate::strings::{INLINE_QUERY_SEPARATOR, NEXT_PAGE, WORD_CONFIRMATION_PREFIX};
+use crate::{
+    bot::{
+        command::settings,
+        payload::{CallbackPayload, PayloadError},
+        spoiler_creation,
+    },
+    state::{
+        rate_limit::{Action, Subject},
+        reveal::RevealPolicy,
+        spoiler::Content,
+        token::TokenPurpose,
+    },
+    util::start_url,
+    State,
+};
 
 /// The maximum length of an Telegram alert.
 ///
-/// A telegram alert [can only be up to 200 characters long][tg docs]. If the content longer than
-/// that, it needs to be sent in a private message instead.
+/// A telegram alert [can only be up to 200 characters long][tg docs]. Longer texts are split into
+/// [pages](alert_pages), and if there are too many of them, the content needs to be sent in a
+/// private message instead.
 ///
 /// [tg docs]: https://core.telegram.org/bots/api#answercallbackquery
-static MAX_ALERT_LENGTH: usize = 200;
+pub(crate) static MAX_ALERT_LENGTH: usize = 200;
+
+/// The maximum number of alerts a text is split into before it is sent in a private message.
+pub(crate) static MAX_ALERT_PAGES: usize = 5;
+
+/// The number of characters reserved at the end of each page for its number, e.g. `\n(2/5)`.
+static PAGE_NUMBER_LENGTH: usize = 8;
+
+/// Returns the label and callback data of each button below a spoiler using the given policy,
+/// signed with the given secret.
+///
+/// If the spoiler is [paged](is_paged), a button showing the next page is added.
+///
+/// The spoiler id may be given with or without the [`INLINE_QUERY_SEPARATOR`] prefix.
+///
+/// Returns `None` if the data of a button does not fit into [callback data].
+///
+/// [callback data]: crate::bot::payload::MAX_CALLBACK_DATA_LENGTH
+pub(crate) fn spoiler_buttons(
+    policy: RevealPolicy,
+    spoiler_id: &str,
+    paged: bool,
+    secret: &[u8],
+) -> Option<Vec<(String, String)>> {
+    let spoiler_id = spoiler_id.trim_start_matches(INLINE_QUERY_SEPARATOR);
+    let spoiler_id = spoiler_id.to_string();
+    let payloads = match policy {
+        RevealPolicy::Confirm => vec![
+            CallbackPayload::Reveal {
+                policy,
+                spoiler_id: spoiler_id.clone(),
+            },
+            CallbackPayload::Confirm {
+                spoiler_id: spoiler_id.clone(),
+            },
+        ],
+        _ => vec![CallbackPayload::Reveal {
+            policy,
+            spoiler_id: spoiler_id.clone(),
+        }],
+    };
+
+    let mut buttons: Vec<(String, String)> = policy
+        .button_labels()
+        .into_iter()
+        .zip(payloads.iter().map(|payload| payload.encode(secret)))
+        .map(|(label, data)| Some((label, data?)))
+        .collect::<Option<_>>()?;
+    if paged {
+        buttons.push((
+            NEXT_PAGE.to_string(),
+            CallbackPayload::NextPage { spoiler_id }.encode(secret)?,
+        ));
+    }
+    Some(buttons)
+}
+
+/// Splits the text into pages that each fit into an alert.
+///
+/// A text that fits as a whole is returned as is. Otherwise pages break at whitespace where
+/// possible and end with their number, such as `(1/3)`. Lengths are counted in characters, so
+/// multibyte characters are never split.
+pub(crate) fn alert_pages(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= MAX_ALERT_LENGTH {
+        return vec![text.to_string()];
+    }
+
+    let limit = MAX_ALERT_LENGTH - PAGE_NUMBER_LENGTH;
+    let mut pages = Vec::new();
+    let mut rest = &chars[..];
+    while !rest.is_empty() {
+        let mut end = rest.len().min(limit);
+        if end < rest.len() {
+            // break after the last whitespace, unless that would leave the page mostly empty
+            if let Some(space) = rest[..=end].iter().rposition(|c| c.is_whitespace()) {
+                if space >= limit / 2 {
+                    end = space;
+                }
+            }
+        }
+
+        let page: String = rest[..end].iter().collect();
+        let page = page.trim();
+        if !page.is_empty() {
+            pages.push(page.to_string());
+        }
+        rest = &rest[end..];
+    }
+
+    let count = pages.len();
+    pages
+        .into_iter()
+        .enumerate()
+        .map(|(i, page)| format!("{}\n({}/{})", page, i + 1, count))
+        .collect()
+}
+
+/// Returns `true` if the content is a text that is revealed in more than one alert.
+pub(crate) fn is_paged(content: &Content) -> bool {
+    match content {
+        Content::Text(text) | Content::String(text) => {
+            (2..=MAX_ALERT_PAGES).contains(&alert_pages(text).len())
+        }
+        _ => false,
+    }
+}
 
 /// Data callback handler
 ///
-/// Receives a spoiler_id as argument.
-/// If the id starts with `maj_`, instruct the handler to open the spoiler in a major fashion.
-pub(crate) async fn data_callback(context: Arc<DataCallback>, state: Arc<State>) {
-    if !context.data.contains(INLINE_QUERY_SEPARATOR) {
-        // useless data callback. We only process queries that have an id
-        return;
-    }
-
-    let spoiler_id = context
-        .data
-        .clone()
-        .rsplit(MAJOR_SPOILER_IDENTIFIER)
-        .collect::<Vec<&str>>()[0]
-        .to_string()
-        .split(INLINE_QUERY_SEPARATOR)
-        .collect::<String>();
-
-    if context.data.starts_with(MAJOR_SPOILER_IDENTIFIER)
-        && state.needs_to_tap_once_more(&context.from.id, &spoiler_id.to_string())
-    {
-        if let Err(e) = context.notify(TAP_AGAIN_TO_SHOW_SPOILER).call().await {
-            dbg!(e);
-            return;
+/// Decodes the [`CallbackPayload`] of the tapped button. Buttons that were created by an older
+/// version of the bot, or carry a payload that is not understood, get a notice instead.
+///
+/// Taps on spoiler buttons reveal the spoiler once the user made enough effort according to the
+/// spoiler's [`RevealPolicy`]:
+/// - [`Taps`](RevealPolicy::Taps) are counted until the required number is reached,
+/// - [`Confirm`](RevealPolicy::Confirm) requires tapping the confirmation button afterwards and
+/// - [`TypeWord`](RevealPolicy::TypeWord) sends the user to a private chat to type a word.
+///
+/// A tap on the button offering a blurred copy of a media spoiler sends that copy, see
+/// [`send_blurred`](spoiler_creation::send_blurred).
+///
+/// Each user may only tap spoiler buttons as often as the [reveal limit] allows.
+///
+/// Taps on the buttons of the group settings menu are handed over to
+/// [`settings_callback`](settings::settings_callback).
+///
+/// [reveal limit]: crate::state::rate_limit::RateLimits#structfield.reveal
+pub(crate) async fn data_callback(
+    bot: Bot,
+    query: CallbackQuery,
+    state: Arc<State>,
+) -> ResponseResult<()> {
+    let data = match &query.data {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+    let payload = match CallbackPayload::decode(data, &state.config.secret) {
+        Ok(payload) => payload,
+        Err(error) => {
+            let notice = match error {
+                // buttons signed with a previous secret are outdated as well
+                PayloadError::Outdated | PayloadError::Forged => OUTDATED_BUTTON,
+                PayloadError::Unknown => UNKNOWN_BUTTON,
+            };
+            notify(&bot, &query, notice).await;
+            return Ok(());
+        }
+    };
+    let spoiler_id = match &payload {
+        CallbackPayload::ChangeSetting(setting) => {
+            return settings::settings_callback(bot, query, state, *setting).await;
         }
+        CallbackPayload::Reveal { spoiler_id, .. }
+        | CallbackPayload::Confirm { spoiler_id }
+        | CallbackPayload::NextPage { spoiler_id }
+        | CallbackPayload::Blur { spoiler_id } => spoiler_id.clone(),
+    };
+
+    // reveals are limited per user only, so busy groups do not lock out everyone at once
+    let user = query.from.id;
+    if !state.allow(Action::Reveal, &[Subject::User(user)]) {
+        alert(&bot, &query, SLOW_DOWN).await;
+        return Ok(());
     }
 
-    match state.get_spoiler(&spoiler_id) {
-        Some(spoiler) => {
-            match &spoiler.content {
-                Content::Text(text) => {
-                    if text.value.chars().count() <= MAX_ALERT_LENGTH {
-                        // 200 is the max limit for an alert
-                        if let Err(e) = context.alert(&text.value).call().await {
-                            dbg!(e);
-                        }
-                        return;
-                    }
-                }
-                Content::String(text) => {
-                    if text.chars().count() <= MAX_ALERT_LENGTH {
-                        if let Err(e) = context.alert(&text).call().await {
-                            dbg!(e);
-                        }
-                        return;
-                    }
+    let spoiler = match state.get_spoiler(&spoiler_id) {
+        Some(spoiler) => spoiler,
+        None => {
+            notify(&bot, &query, SPOILER_NOT_FOUND).await;
+            return Ok(());
+        }
+    };
+
+    // spoilers posted with a fixed policy are revealed according to it, whatever the button says
+    let payload = match (spoiler.policy, payload) {
+        (Some(policy), CallbackPayload::Reveal { spoiler_id, .. }) => {
+            CallbackPayload::Reveal { policy, spoiler_id }
+        }
+        (Some(policy), CallbackPayload::Confirm { .. }) if policy != RevealPolicy::Confirm => {
+            notify(&bot, &query, UNKNOWN_BUTTON).await;
+            return Ok(());
+        }
+        (_, payload) => payload,
+    };
+
+    let notification = match payload {
+        CallbackPayload::Reveal {
+            policy: RevealPolicy::Taps(taps),
+            ..
+        } => match state.remaining_taps(&user, &spoiler_id, taps) {
+            0 => None,
+            1 => Some(TAP_AGAIN_TO_SHOW_SPOILER.to_string()),
+            remaining => Some(tap_more_times(remaining)),
+        },
+        CallbackPayload::Reveal {
+            policy: RevealPolicy::Confirm,
+            ..
+        } => {
+            state.register_tap(user, &spoiler_id);
+            Some(CONFIRM_TO_SHOW_SPOILER.to_string())
+        }
+        CallbackPayload::Reveal {
+            policy: RevealPolicy::TypeWord,
+            ..
+        } => {
+            let start_param = format!(
+                "{}{}",
+                WORD_CONFIRMATION_PREFIX,
+                state.issue_token(TokenPurpose::TypeWord, &spoiler_id, user)
+            );
+            open_url(&bot, &query, &start_url(&state.bot_username, &start_param)).await;
+            return Ok(());
+        }
+        CallbackPayload::Confirm { .. } => {
+            if state.confirm_tap(&user, &spoiler_id) {
+                None
+            } else {
+                Some(SHOW_SPOILER_FIRST.to_string())
+            }
+        }
+        CallbackPayload::NextPage { .. } => {
+            let pages = match &spoiler.content {
+                Content::Text(text) | Content::String(text) => alert_pages(text),
+                _ => Vec::new(),
+            };
+            match state.next_page(&user, &spoiler_id, pages.len()) {
+                Some(page) => {
+                    alert(&bot, &query, &pages[page]).await;
+                    return Ok(());
                 }
-                _ => {}
+                None => Some(SHOW_SPOILER_FIRST.to_string()),
             }
+        }
+        CallbackPayload::Blur { .. } => {
+            notify(&bot, &query, BLURRED_COPY_SENT).await;
+            spoiler_creation::send_blurred(&bot, user, &spoiler).await;
+            return Ok(());
+        }
+        // handled above
+        CallbackPayload::ChangeSetting(_) => return Ok(()),
+    };
+    if let Some(notification) = notification {
+        notify(&bot, &query, &notification).await;
+        return Ok(());
+    }
 
-            if let Err(e) = context
-                .open_url(
-                    &start_url(
-                        context.clone(),
-                        &format!("{}{}", INLINE_QUERY_SEPARATOR, spoiler_id),
-                    )
-                    .await,
-                )
-                .call()
-                .await
-            {
-                dbg!(e);
+    // Attributed spoilers need to be forwarded in a private chat instead
+    let attributed = spoiler.origin.is_some();
+    match &spoiler.content {
+        Content::Text(text) | Content::String(text) if !attributed => {
+            // too many pages are easier to read in a private chat
+            let pages = alert_pages(text);
+            if pages.len() <= MAX_ALERT_PAGES {
+                if pages.len() > 1 {
+                    state.open_first_page(user, &spoiler_id);
+                }
+                alert(&bot, &query, &pages[0]).await;
+                return Ok(());
             }
         }
-        None => {
-            if let Err(e) = context.notify(SPOILER_NOT_FOUND).call().await {
-                dbg!(e);
-                return;
-            }
+        _ => {}
+    }
+
+    let start_param = format!(
+        "{}{}",
+        INLINE_QUERY_SEPARATOR,
+        state.issue_token(TokenPurpose::Reveal, &spoiler_id, user)
+    );
+    open_url(&bot, &query, &start_url(&state.bot_username, &start_param)).await;
+    Ok(())
+}
+
+/// Shows the given text as a notification to the user who tapped the button.
+pub(crate) async fn notify(bot: &Bot, query: &CallbackQuery, text: &str) {
+    if let Err(e) = bot.answer_callback_query(query.id.clone()).text(text).await {
+        dbg!(e);
+    }
+}
+
+/// Shows the given text in an alert to the user who tapped the button.
+async fn alert(bot: &Bot, query: &CallbackQuery, text: &str) {
+    if let Err(e) = bot
+        .answer_callback_query(query.id.clone())
+        .text(text)
+        .show_alert(true)
+        .await
+    {
+        dbg!(e);
+    }
+}
+
+/// Opens the given URL for the user who tapped the button.
+async fn open_url(bot: &Bot, query: &CallbackQuery, url: &str) {
+    let url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(e) => {
+            dbg!(e);
+            return;
+        }
+    };
+    if let Err(e) = bot.answer_callback_query(query.id.clone()).url(url).await {
+        dbg!(e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        bot::{mock_api::MockApi, recorded},
+        config::Config,
+    };
+
+    /// Taps the button with the given callback data and returns the answer to the tap.
+    async fn tap(api: &MockApi, data: &str, state: &Arc<State>) -> Value {
+        let before = api.calls("answerCallbackQuery").len();
+        data_callback(api.bot(), recorded::callback_query(data), state.clone())
+            .await
+            .unwrap();
+
+        let answers = api.calls("answerCallbackQuery");
+        assert_eq!(answers.len(), before + 1);
+        answers[before].clone()
+    }
+
+    /// Returns the callback data of the buttons below a text spoiler using the given policy.
+    fn buttons(state: &State, policy: RevealPolicy, text: &str) -> Vec<String> {
+        let id = state.create_spoiler(None, Content::Text(text.to_string()), None, None);
+        spoiler_buttons(
+            policy,
+            &id,
+            is_paged(&Content::Text(text.to_string())),
+            &state.config.secret,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(_label, data)| data)
+        .collect()
+    }
+
+    /// Returns the length of each page in characters.
+    fn lengths(pages: &[String]) -> Vec<usize> {
+        pages.iter().map(|page| page.chars().count()).collect()
+    }
+
+    #[test]
+    fn short_texts_fit_into_one_alert() {
+        let text = "a".repeat(MAX_ALERT_LENGTH);
+
+        assert_eq!(alert_pages(&text), [text]);
+        assert_eq!(alert_pages(""), [""]);
+    }
+
+    #[test]
+    fn longer_texts_are_numbered() {
+        let pages = alert_pages(&"a".repeat(MAX_ALERT_LENGTH + 1));
+
+        let first = MAX_ALERT_LENGTH - PAGE_NUMBER_LENGTH;
+        // followed by "\n(1/2)"
+        assert_eq!(
+            lengths(&pages),
+            [first + 6, MAX_ALERT_LENGTH + 1 - first + 6]
+        );
+        assert!(pages[0].ends_with("\n(1/2)"));
+        assert!(pages[1].ends_with("\n(2/2)"));
+    }
+
+    #[test]
+    fn multibyte_characters_count_once() {
+        // each of them takes four bytes
+        let text = "🦉".repeat(MAX_ALERT_LENGTH);
+        assert_eq!(alert_pages(&text), [text]);
+
+        let pages = alert_pages(&"🦉".repeat(MAX_ALERT_LENGTH + 1));
+        assert_eq!(pages.len(), 2);
+        assert!(lengths(&pages)
+            .iter()
+            .all(|&length| length <= MAX_ALERT_LENGTH));
+        assert_eq!(pages.concat().matches('🦉').count(), MAX_ALERT_LENGTH + 1);
+    }
+
+    #[test]
+    fn pages_break_at_whitespace() {
+        let text = "Snape kills Dumbledore. ".repeat(20);
+        let pages = alert_pages(&text);
+
+        assert!(lengths(&pages)
+            .iter()
+            .all(|&length| length <= MAX_ALERT_LENGTH));
+        for page in &pages {
+            let (content, _number) = page.rsplit_once('\n').unwrap();
+            assert!(content.ends_with("Dumbledore."));
+        }
+        let words: Vec<&str> = pages
+            .iter()
+            .flat_map(|page| page.rsplit_once('\n').unwrap().0.split_whitespace())
+            .collect();
+        assert_eq!(words, text.split_whitespace().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn every_page_fits_into_an_alert() {
+        for length in [201, 383, 384, 385, 1000, 2000] {
+            let text: String = "ä ö ü 🦉 ".chars().cycle().take(length).collect();
+            let pages = alert_pages(&text);
+
+            assert!(lengths(&pages)
+                .iter()
+                .all(|&length| length <= MAX_ALERT_LENGTH));
         }
     }
+
+    #[test]
+    fn texts_with_too_many_pages_are_not_paged() {
+        let fits = "a".repeat(MAX_ALERT_PAGES * (MAX_ALERT_LENGTH - PAGE_NUMBER_LENGTH));
+        let too_long = "a".repeat(fits.len() + 1);
+
+        assert!(!is_paged(&Content::Text("a".repeat(MAX_ALERT_LENGTH))));
+        assert!(is_paged(&Content::Text(fits)));
+        assert!(!is_paged(&Content::String(too_long)));
+    }
+
+    #[tokio::test]
+    async fn minor_spoilers_are_revealed_in_an_alert() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let data = buttons(&state, RevealPolicy::Taps(1), "Snape kills Dumbledore");
+
+        let answer = tap(&api, &data[0], &state).await;
+
+        assert_eq!(answer["callback_query_id"], "4382bfdwdsb323b2d9");
+        assert_eq!(answer["text"], "Snape kills Dumbledore");
+        assert_eq!(answer["show_alert"], true);
+    }
+
+    #[tokio::test]
+    async fn major_spoilers_need_a_second_tap() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let data = buttons(&state, RevealPolicy::Taps(2), "Snape kills Dumbledore");
+
+        let first = tap(&api, &data[0], &state).await;
+        let second = tap(&api, &data[0], &state).await;
+
+        assert_eq!(first["text"], TAP_AGAIN_TO_SHOW_SPOILER);
+        assert_ne!(first["show_alert"], true);
+        assert_eq!(second["text"], "Snape kills Dumbledore");
+        assert_eq!(second["show_alert"], true);
+    }
+
+    #[tokio::test]
+    async fn confirmed_spoilers_need_the_confirmation_button() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let data = buttons(&state, RevealPolicy::Confirm, "Snape kills Dumbledore");
+
+        let early = tap(&api, &data[1], &state).await;
+        let show = tap(&api, &data[0], &state).await;
+        let confirm = tap(&api, &data[1], &state).await;
+
+        assert_eq!(early["text"], SHOW_SPOILER_FIRST);
+        assert_eq!(show["text"], CONFIRM_TO_SHOW_SPOILER);
+        assert_eq!(confirm["text"], "Snape kills Dumbledore");
+        assert_eq!(confirm["show_alert"], true);
+    }
+
+    #[tokio::test]
+    async fn hidden_spoilers_open_a_private_chat() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let data = buttons(&state, RevealPolicy::TypeWord, "Snape kills Dumbledore");
+
+        let answer = tap(&api, &data[0], &state).await;
+
+        let url = answer["url"].as_str().unwrap();
+        let prefix = format!(
+            "https://t.me/spoilerowobot?start={}",
+            WORD_CONFIRMATION_PREFIX
+        );
+        assert!(url.starts_with(&prefix), "{}", url);
+    }
+
+    #[tokio::test]
+    async fn long_spoilers_are_revealed_page_by_page() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let text = "Snape kills Dumbledore. ".repeat(10);
+        let data = buttons(&state, RevealPolicy::Taps(1), &text);
+        let pages = alert_pages(&text);
+        assert_eq!(data.len(), 2);
+
+        let early = tap(&api, &data[1], &state).await;
+        let first = tap(&api, &data[0], &state).await;
+        let second = tap(&api, &data[1], &state).await;
+
+        assert_eq!(early["text"], SHOW_SPOILER_FIRST);
+        assert_eq!(first["text"], pages[0]);
+        assert_eq!(second["text"], pages[1]);
+        assert_eq!(second["show_alert"], true);
+    }
+
+    #[tokio::test]
+    async fn media_spoilers_are_sent_blurred() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let photo = recorded::group_message(json!({
+            "photo": [{
+                "file_id": "AgACAgIAAxkBAAIBZ2VhY2hfcGhvdG8",
+                "file_unique_id": "AQADAgAT",
+                "width": 1280,
+                "height": 720,
+                "file_size": 81234,
+            }],
+            "caption": "Snape kills Dumbledore",
+        }));
+        let content = Content::from_message(&photo).unwrap();
+        let id = state.create_spoiler(Some("Harry Potter".to_string()), content, None, None);
+        let data = CallbackPayload::Blur { spoiler_id: id }
+            .encode(&state.config.secret)
+            .unwrap();
+
+        let answer = tap(&api, &data, &state).await;
+
+        assert_eq!(answer["text"], BLURRED_COPY_SENT);
+        let sent = api.call("sendPhoto");
+        assert_eq!(sent["chat_id"], recorded::USER_ID);
+        assert_eq!(sent["photo"], "AgACAgIAAxkBAAIBZ2VhY2hfcGhvdG8");
+        assert_eq!(sent["has_spoiler"], true);
+        assert_eq!(
+            sent["caption"],
+            "<b>Harry Potter</b>\n<tg-spoiler>Snape kills Dumbledore</tg-spoiler>"
+        );
+    }
+
+    #[tokio::test]
+    async fn outdated_buttons_and_removed_spoilers_get_a_notice() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let data = buttons(&state, RevealPolicy::Taps(1), "Snape kills Dumbledore");
+        let (other, _expirations) = recorded::state(Config::default());
+        let foreign = buttons(&other, RevealPolicy::Taps(1), "Snape kills Dumbledore");
+        let removed = CallbackPayload::Reveal {
+            policy: RevealPolicy::Taps(1),
+            spoiler_id: "abc123".to_string(),
+        }
+        .encode(&state.config.secret)
+        .unwrap();
+
+        assert_eq!(
+            tap(&api, &foreign[0], &state).await["text"],
+            OUTDATED_BUTTON
+        );
+        assert_eq!(tap(&api, &removed, &state).await["text"], SPOILER_NOT_FOUND);
+        assert_eq!(tap(&api, &data[0], &state).await["show_alert"], true);
+    }
 }
@@ -1,23 +1,60 @@
 //! Handles the `/cancel` command.
 use std::sync::Arc;
 
-use tbot::contexts::{methods::ChatMethods, Command, Text};
+use teloxide::prelude::*;
 
 use crate::state::State;
 use crate::strings::bot_replies::SPOILER_CREATION_CANCELLED;
+use crate::util;
 
 /// Handle the `/cancel` command.
 ///
 /// This will cancel the spoiler creation process.
-pub(crate) async fn cancel(context: Arc<Command<Text>>, state: Arc<State>) {
-    let user = &context.from.as_ref().unwrap().id;
+pub(crate) async fn cancel(bot: Bot, message: Message, state: Arc<State>) -> ResponseResult<()> {
+    let user = match &message.from {
+        Some(user) => user.id,
+        None => return Ok(()),
+    };
 
-    let message = match state.cancel_spoiler_creation(user) {
+    let reply = match state.cancel_spoiler_creation(&user) {
         None => "You were not creating a spoiler.",
         Some(..) => SPOILER_CREATION_CANCELLED,
     };
 
-    if let Err(e) = context.send_message_in_reply(message).call().await {
-        dbg!(e.to_string());
+    util::reply(&bot, &message, reply).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bot::{mock_api::MockApi, recorded},
+        config::Config,
+    };
+
+    #[tokio::test]
+    async fn tells_whether_a_spoiler_creation_was_cancelled() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let message = recorded::private_message("/cancel");
+
+        cancel(api.bot(), message.clone(), state.clone())
+            .await
+            .unwrap();
+        state.set_waiting_for_spoiler(UserId(recorded::USER_ID));
+        cancel(api.bot(), message, state.clone()).await.unwrap();
+
+        let replies = api.calls("sendMessage");
+        assert_eq!(replies[0]["text"], "You were not creating a spoiler.");
+        assert_eq!(replies[1]["text"], SPOILER_CREATION_CANCELLED);
+        for reply in &replies {
+            assert_eq!(reply["chat_id"], recorded::USER_ID);
+            assert_eq!(reply["reply_parameters"]["message_id"], 42);
+        }
+        assert!(state
+            .cancel_spoiler_creation(&UserId(recorded::USER_ID))
+            .is_none());
     }
 }
@@ -0,0 +1,91 @@
+//! Handles the `/spoilerfilter` command.
+use std::sync::Arc;
+
+use teloxide::prelude::*;
+
+use crate::{
+    bot::command::Command,
+    state::{filter::Filter, State},
+    strings::bot_replies::{
+        filter_list, filter_removed, invalid_regex, COULD_NOT_FETCH_ADMINS, FILTER_ADDED,
+        FILTER_NOT_FOUND, FILTER_USAGE, FILTER_WOULD_MATCH, FILTER_WOULD_NOT_MATCH,
+        ONLY_ADMINS_CAN_CHANGE_SETTINGS,
+    },
+    util,
+};
+
+/// Handle the `/spoilerfilter` command sent from a group.
+///
+/// Lets admins manage the filters that automatically turn matching messages into spoilers, see
+/// [`FILTER_USAGE`] for the available subcommands. Anyone may list the filters or test whether a
+/// sample message would be spoiled.
+pub(crate) async fn spoiler_filter(
+    bot: Bot,
+    message: Message,
+    command: Command,
+    state: Arc<State>,
+) -> ResponseResult<()> {
+    let text = command.text.trim();
+    let (subcommand, argument) = match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim()),
+        None => (text, ""),
+    };
+
+    let reply = match subcommand {
+        "list" => filter_list(&state.get_chat_settings(&message.chat.id).filters),
+        "test" if !argument.is_empty() => {
+            if state.matches_filter(&message.chat.id, argument) {
+                FILTER_WOULD_MATCH.to_string()
+            } else {
+                FILTER_WOULD_NOT_MATCH.to_string()
+            }
+        }
+        "add" | "regex" | "remove" if !argument.is_empty() => {
+            match util::sent_by_admin(&bot, &message).await {
+                Ok(true) => change_filters(&message, &state, subcommand, argument),
+                Ok(false) => ONLY_ADMINS_CAN_CHANGE_SETTINGS.to_string(),
+                Err(e) => {
+                    dbg!(e);
+                    COULD_NOT_FETCH_ADMINS.to_string()
+                }
+            }
+        }
+        _ => FILTER_USAGE.to_string(),
+    };
+
+    util::reply(&bot, &message, &reply).await;
+    Ok(())
+}
+
+/// Adds or removes a filter as requested by an admin and returns the reply to send.
+fn change_filters(message: &Message, state: &State, subcommand: &str, argument: &str) -> String {
+    if subcommand == "remove" {
+        return match argument.parse::<usize>() {
+            Ok(number) if number > 0 => match state.remove_filter(&message.chat.id, number - 1) {
+                Some(filter) => filter_removed(&filter),
+                None => FILTER_NOT_FOUND.to_string(),
+            },
+            _ => FILTER_NOT_FOUND.to_string(),
+        };
+    }
+
+    let argument = argument.to_string();
+    let active_for = util::parse_duration(&argument);
+    let pattern = util::strip_expiration_suffix(&argument);
+    let pattern = pattern.trim();
+    if pattern.is_empty() {
+        return FILTER_USAGE.to_string();
+    }
+
+    let filter = if subcommand == "regex" {
+        match Filter::regex(pattern, active_for) {
+            Ok(filter) => filter,
+            Err(e) => return invalid_regex(e),
+        }
+    } else {
+        Filter::keyword(pattern, active_for)
+    };
+    state.add_filter(message.chat.id, filter);
+
+    FILTER_ADDED.to_string()
+}
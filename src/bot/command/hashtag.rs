@@ -0,0 +1,66 @@
+//! Handles the `/spoilerhashtags` command.
+use std::sync::Arc;
+
+use teloxide::prelude::*;
+
+use crate::{
+    bot::command::Command,
+    state::State,
+    strings::bot_replies::{
+        hashtag_list, COULD_NOT_FETCH_ADMINS, HASHTAG_ADDED, HASHTAG_EXISTS, HASHTAG_NOT_FOUND,
+        HASHTAG_REMOVED, HASHTAG_USAGE, ONLY_ADMINS_CAN_CHANGE_SETTINGS,
+    },
+    util,
+};
+
+/// Handle the `/spoilerhashtags` command sent from a group.
+///
+/// Lets admins opt the group into turning messages that start with one of the given hashtags into
+/// spoilers, see [`HASHTAG_USAGE`] for the available subcommands. Anyone may list the hashtags.
+pub(crate) async fn spoiler_hashtags(
+    bot: Bot,
+    message: Message,
+    command: Command,
+    state: Arc<State>,
+) -> ResponseResult<()> {
+    let mut arguments = command.text.split_whitespace();
+    let subcommand = arguments.next().unwrap_or("");
+    let hashtag = arguments.next().map(|hashtag| {
+        if hashtag.starts_with('#') {
+            hashtag.to_string()
+        } else {
+            format!("#{}", hashtag)
+        }
+    });
+
+    let reply: String = match (subcommand, hashtag) {
+        ("list", _) => hashtag_list(&state.get_chat_settings(&message.chat.id).hashtags),
+        ("add", Some(hashtag)) | ("remove", Some(hashtag)) => {
+            match util::sent_by_admin(&bot, &message).await {
+                Ok(true) if subcommand == "add" => {
+                    if state.add_hashtag(message.chat.id, &hashtag) {
+                        HASHTAG_ADDED.into()
+                    } else {
+                        HASHTAG_EXISTS.into()
+                    }
+                }
+                Ok(true) => {
+                    if state.remove_hashtag(&message.chat.id, &hashtag) {
+                        HASHTAG_REMOVED.into()
+                    } else {
+                        HASHTAG_NOT_FOUND.into()
+                    }
+                }
+                Ok(false) => ONLY_ADMINS_CAN_CHANGE_SETTINGS.into(),
+                Err(e) => {
+                    dbg!(e);
+                    COULD_NOT_FETCH_ADMINS.into()
+                }
+            }
+        }
+        _ => HASHTAG_USAGE.into(),
+    };
+
+    util::reply(&bot, &message, &reply).await;
+    Ok(())
+}
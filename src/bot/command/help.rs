@@ -1,27 +1,43 @@
 //! Handles the `/help` command.
 use std::sync::Arc;
 
-use tbot::contexts::{fields::Context, methods::ChatMethods, Command, Text};
+use teloxide::prelude::*;
 
-use crate::state::State;
 use crate::strings::bot_replies::help_text;
+use crate::{util, State};
 
 /// Handle the `/help` command.
 ///
 /// This will send a reply with a hint on how to use this bot.
-pub(crate) async fn help(context: Arc<Command<Text>>, _state: Arc<State>) {
-    let bot_username = match context.bot().get_me().call().await {
-        Ok(me) => me.user.username.unwrap_or(me.user.first_name),
-        Err(err) => {
-            dbg!(err.to_string());
-            "my bot username".to_string()
-        }
+pub(crate) async fn help(bot: Bot, message: Message, state: Arc<State>) -> ResponseResult<()> {
+    let bot_username = state.bot_username.clone();
+    util::reply(&bot, &message, &help_text(bot_username)).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bot::{mock_api::MockApi, recorded},
+        config::Config,
     };
-    if let Err(e) = context
-        .send_message_in_reply(&help_text(bot_username))
-        .call()
+
+    #[tokio::test]
+    async fn replies_with_the_help_text() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+
+        help(
+            api.bot(),
+            recorded::private_message("/help"),
+            Arc::new(state),
+        )
         .await
-    {
-        dbg!(e);
+        .unwrap();
+
+        let reply = api.call("sendMessage");
+        assert_eq!(reply["text"], help_text("spoilerowobot".to_string()));
+        assert_eq!(reply["reply_parameters"]["message_id"], 42);
     }
 }
@@ -1,7 +1,122 @@
 //! # Bot Commands
 //!
 //! Following modules define the behaviour of the bot upon receiving a command.
+use teloxide::types::{Me, Message, MessageEntityKind};
+
 pub(crate) mod cancel;
+pub(crate) mod filter;
+pub(crate) mod hashtag;
 pub(crate) mod help;
+pub(crate) mod settings;
 pub(crate) mod spoiler;
 pub(crate) mod start;
+pub(crate) mod stats;
+
+/// A command sent to the bot, such as `/spoiler@spoilerowobot title`.
+#[derive(Clone, Debug)]
+pub(crate) struct Command {
+    /// The name of the command, without the leading slash and the bot's username.
+    pub(crate) name: String,
+    /// The username of the bot the command was sent to, if it was given.
+    pub(crate) username: Option<String>,
+    /// The text following the command, with leading whitespace removed.
+    pub(crate) text: String,
+}
+
+impl Command {
+    /// Parses the command the text message starts with.
+    ///
+    /// Returns `None` if the message does not start with a command.
+    pub(crate) fn parse(message: &Message) -> Option<Command> {
+        let text = message.text()?;
+        let starts_with_command = message.entities()?.first().is_some_and(|entity| {
+            entity.kind == MessageEntityKind::BotCommand && entity.offset == 0
+        });
+        if !starts_with_command {
+            return None;
+        }
+
+        let command = text.split_whitespace().next()?;
+        let mut parts = command[1..].splitn(2, '@');
+        Some(Command {
+            name: parts.next()?.to_string(),
+            username: parts.next().map(str::to_string),
+            text: text[command.len()..].trim_start().to_string(),
+        })
+    }
+
+    /// Returns `true` if this is the command with the given name, directed to this bot.
+    ///
+    /// Commands such as `/command@username` are only directed to the bot with that username.
+    pub(crate) fn is(&self, name: &str, me: &Me) -> bool {
+        let for_this_bot = match &self.username {
+            Some(username) => me.user.username.as_deref() == Some(username.as_str()),
+            None => true,
+        };
+        for_this_bot && self.name == name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::bot::recorded::{self, me};
+
+    /// Returns a text message sent to a group, starting with an entity of the given kind and
+    /// length.
+    fn group_message(text: &str, entity: &str, length: usize) -> Message {
+        recorded::group_message(json!({
+            "text": text,
+            "entities": [{ "offset": 0, "length": length, "type": entity }],
+        }))
+    }
+
+    #[test]
+    fn parses_commands_for_this_bot() {
+        let message = group_message(
+            "/spoiler@spoilerowobot  title:::reason /2d",
+            "bot_command",
+            22,
+        );
+        let command = Command::parse(&message).unwrap();
+
+        assert_eq!(command.name, "spoiler");
+        assert_eq!(command.username.as_deref(), Some("spoilerowobot"));
+        assert_eq!(command.text, "title:::reason /2d");
+        assert!(command.is("spoiler", &me()));
+        assert!(!command.is("start", &me()));
+    }
+
+    #[test]
+    fn parses_commands_without_username() {
+        let message = group_message("/start", "bot_command", 6);
+        let command = Command::parse(&message).unwrap();
+
+        assert_eq!(command.name, "start");
+        assert_eq!(command.username, None);
+        assert_eq!(command.text, "");
+        assert!(command.is("start", &me()));
+    }
+
+    #[test]
+    fn ignores_commands_for_other_bots() {
+        let message = group_message("/spoiler@otherbot", "bot_command", 17);
+
+        assert!(!Command::parse(&message).unwrap().is("spoiler", &me()));
+    }
+
+    #[test]
+    fn ignores_messages_not_starting_with_a_command() {
+        assert!(Command::parse(&group_message("#spoiler text", "hashtag", 8)).is_none());
+        let message = recorded::message(json!({
+            "message_id": 1366,
+            "from": { "id": 10000, "is_bot": false, "first_name": "Lara" },
+            "chat": { "id": 10000, "first_name": "Lara", "type": "private" },
+            "date": 1700000000,
+            "text": "just text",
+        }));
+        assert!(Command::parse(&message).is_none());
+    }
+}
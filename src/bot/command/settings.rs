@@ -0,0 +1,215 @@
+//! Handles the `/spoilersettings` command and the settings menu it opens.
+use std::sync::Arc;
+
+use teloxide::{
+    prelude::*,
+    sugar::request::RequestReplyExt,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{
+    bot::{callback, command::Command, payload::CallbackPayload},
+    state::{
+        chat_settings::{ChatSettings, ModerationPolicy},
+        State,
+    },
+    strings::bot_replies::{
+        spoiler_settings_changed, spoiler_settings_text, COULD_NOT_FETCH_ADMINS,
+        ONLY_ADMINS_CAN_CHANGE_SETTINGS, SETTINGS_SAVED, TITLE_TEMPLATE_CHANGED,
+    },
+    util,
+};
+
+/// A setting that can be changed by tapping a button of the settings menu.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(crate) enum Setting {
+    /// Who may use `/spoiler`.
+    Moderation,
+    /// When spoilers expire by default.
+    Expiration,
+    /// How spoilers are revealed.
+    Mode,
+    /// Whether the author of spoiled messages is shown.
+    Author,
+}
+
+impl Setting {
+    /// All settings, in the order they appear in the menu.
+    const ALL: [Setting; 4] = [
+        Setting::Moderation,
+        Setting::Expiration,
+        Setting::Mode,
+        Setting::Author,
+    ];
+
+    /// The name identifying this setting in callback payloads.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Setting::Moderation => "moderation",
+            Setting::Expiration => "expiration",
+            Setting::Mode => "mode",
+            Setting::Author => "author",
+        }
+    }
+
+    /// Returns the setting with the given name.
+    pub(crate) fn from_name(name: &str) -> Option<Setting> {
+        Setting::ALL
+            .iter()
+            .copied()
+            .find(|setting| setting.name() == name)
+    }
+
+    /// The label of the button, showing the current value of this setting.
+    fn label(self, settings: &ChatSettings) -> String {
+        match self {
+            Setting::Moderation => format!("Who may /spoiler: {}", settings.moderation.name()),
+            Setting::Expiration => format!("Expires after: {}", settings.default_expiration_name()),
+            Setting::Mode => format!(
+                "Mode: {} ({})",
+                settings.reveal.spoiler_name(),
+                settings.reveal.description()
+            ),
+            Setting::Author => {
+                if settings.show_author {
+                    "Show author: yes".to_string()
+                } else {
+                    "Show author: no".to_string()
+                }
+            }
+        }
+    }
+
+    /// Switches this setting to its next value.
+    fn change(self, settings: &mut ChatSettings) {
+        match self {
+            Setting::Moderation => settings.moderation = settings.moderation.next(),
+            Setting::Expiration => settings.next_default_expiration(),
+            Setting::Mode => settings.reveal = settings.reveal.next(),
+            Setting::Author => settings.show_author = !settings.show_author,
+        }
+    }
+}
+
+/// Handle the `/spoilersettings` command sent from a group.
+///
+/// Without an argument, the bot replies with a menu of the group's settings, which admins can
+/// change by tapping its buttons. Admins can also pass
+/// - `title` followed by a new [title template] or
+/// - the name of a [`ModerationPolicy`]
+///
+/// to change the respective setting right away.
+///
+/// [title template]: ChatSettings#structfield.title_template
+pub(crate) async fn spoiler_settings(
+    bot: Bot,
+    message: Message,
+    command: Command,
+    state: Arc<State>,
+) -> ResponseResult<()> {
+    let argument = command.text.trim();
+
+    if argument.is_empty() {
+        let settings = state.get_chat_settings(&message.chat.id);
+
+        if let Err(e) = bot
+            .send_message(message.chat.id, spoiler_settings_text(&settings))
+            .reply_to(message.id)
+            .reply_markup(menu(&settings, &state.config.secret))
+            .await
+        {
+            dbg!(e);
+        }
+        return Ok(());
+    }
+
+    let reply = match util::sent_by_admin(&bot, &message).await {
+        Ok(true) => change_setting(&message, &state, argument),
+        Ok(false) => ONLY_ADMINS_CAN_CHANGE_SETTINGS.to_string(),
+        Err(e) => {
+            dbg!(e);
+            COULD_NOT_FETCH_ADMINS.to_string()
+        }
+    };
+
+    util::reply(&bot, &message, &reply).await;
+    Ok(())
+}
+
+/// Changes a setting as requested by an admin and returns the reply to send.
+fn change_setting(message: &Message, state: &State, argument: &str) -> String {
+    if let Some(template) = argument.strip_prefix("title") {
+        let template = template.trim().to_string();
+        state.update_chat_settings(message.chat.id, |settings| {
+            settings.title_template = if template.is_empty() {
+                ChatSettings::default().title_template
+            } else {
+                template
+            }
+        });
+        return TITLE_TEMPLATE_CHANGED.to_string();
+    }
+
+    match ModerationPolicy::from_name(argument) {
+        Some(policy) => {
+            state.update_chat_settings(message.chat.id, |settings| settings.moderation = policy);
+            spoiler_settings_changed(policy)
+        }
+        None => spoiler_settings_text(&state.get_chat_settings(&message.chat.id)),
+    }
+}
+
+/// Handle taps on the buttons of the settings menu.
+///
+/// Only admins of the group may change its settings. After a change, the menu gets updated to
+/// reflect the new settings.
+pub(crate) async fn settings_callback(
+    bot: Bot,
+    query: CallbackQuery,
+    state: Arc<State>,
+    setting: Setting,
+) -> ResponseResult<()> {
+    let message = match &query.message {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+    let chat_id = message.chat().id;
+
+    let is_admin = match bot.get_chat_administrators(chat_id).await {
+        Ok(admins) => util::is_admin(&admins, query.from.id),
+        Err(e) => {
+            dbg!(e);
+            callback::notify(&bot, &query, COULD_NOT_FETCH_ADMINS).await;
+            return Ok(());
+        }
+    };
+    if !is_admin {
+        callback::notify(&bot, &query, ONLY_ADMINS_CAN_CHANGE_SETTINGS).await;
+        return Ok(());
+    }
+
+    let settings = state.update_chat_settings(chat_id, |settings| setting.change(settings));
+
+    if let Err(e) = bot
+        .edit_message_text(chat_id, message.id(), spoiler_settings_text(&settings))
+        .reply_markup(menu(&settings, &state.config.secret))
+        .await
+    {
+        dbg!(e);
+    }
+    callback::notify(&bot, &query, SETTINGS_SAVED).await;
+    Ok(())
+}
+
+/// Returns the settings menu, with a button for each setting signed with the given secret.
+///
+/// Settings whose button data does not fit into callback data are left out.
+fn menu(settings: &ChatSettings, secret: &[u8]) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(Setting::ALL.iter().filter_map(|setting| {
+        let data = CallbackPayload::ChangeSetting(*setting).encode(secret)?;
+        Some([InlineKeyboardButton::callback(
+            setting.label(settings),
+            data,
+        )])
+    }))
+}
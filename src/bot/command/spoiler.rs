@@ -1,171 +1,426 @@
 //! Handles the `/spoiler` command.
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use tbot::{
-    contexts::{methods::ChatMethods, Command, Text},
+use teloxide::{
+    prelude::*,
     types::{
-        chat::member::Status,
-        keyboard::inline::{Button, ButtonKind, Markup},
-        message::Kind,
-        parameters,
+        Administrator, ChatMember, ChatMemberKind, InlineKeyboardButton, InlineKeyboardMarkup,
+        MessageId, ParseMode,
     },
+    utils::html,
 };
 
 use crate::{
-    state::{spoiler::Content, State},
+    bot::{callback, command::Command},
+    state::{
+        rate_limit::{Action, Subject},
+        reveal::RevealPolicy,
+        spoiler::{Content, Origin},
+        State,
+    },
     strings::{
-        bot_replies::{NOT_AN_ADMIN, NO_DELETE_PERMISSION},
-        INLINE_QUERY_SEPARATOR, SHOW_SPOILER,
+        bot_replies::{
+            COULD_NOT_FETCH_ADMINS, NOT_ALLOWED_TO_SPOILER, NOT_AN_ADMIN, NO_DELETE_PERMISSION,
+            SLOW_DOWN,
+        },
+        SPOILER_TITLE_SEPARATOR,
     },
+    util,
 };
 
 /// Handles the `/spoiler` command.
 ///
-/// When replying to someone else's message with `/spoiler`, it will create a new spoiler with the
-/// message in reply to as content and finally delete the original message from the group.
+/// When replying to someone else's message with `/spoiler`, it will replace the message in reply
+/// to by a spoiler, see [`spoil_message`].
+///
+/// The text following the command can be used to set the spoiler's title, a reason that is shown
+/// to the group and when the spoiler expires, see [`Arguments`].
+///
+/// This requires permission for the bot to delete messages in the group. Who may use the command
+/// is decided by the group's [moderation policy].
 ///
-/// This requires permission for the bot to delete messages in the group.
-pub(crate) async fn spoiler(context: Arc<Command<Text>>, state: Arc<State>) {
-    let bot_id = context.bot.get_me().call().await.unwrap().user.id;
+/// [moderation policy]: crate::state::chat_settings::ModerationPolicy
+pub(crate) async fn spoiler(
+    bot: Bot,
+    message: Message,
+    command: Command,
+    state: Arc<State>,
+) -> ResponseResult<()> {
+    if let Some(user) = &message.from {
+        let subjects = [Subject::User(user.id), Subject::Chat(message.chat.id)];
+        if !state.allow(Action::Spoiler, &subjects) {
+            util::reply(&bot, &message, SLOW_DOWN).await;
+            return Ok(());
+        }
+    }
 
-    if !can_delete_messages(context.clone()).await {
-        return;
+    let admins = match bot.get_chat_administrators(message.chat.id).await {
+        Ok(admins) => admins,
+        Err(e) => {
+            dbg!(e);
+            util::reply(&bot, &message, COULD_NOT_FETCH_ADMINS).await;
+            return Ok(());
+        }
+    };
+
+    if !can_delete_messages(&bot, &message, &admins, state.bot_id).await
+        || !may_spoiler(&bot, &message, &state, &admins).await
+    {
+        return Ok(());
     }
 
-    // create spoiler
-    if let Some(message) = context.reply_to.clone() {
-        match message.kind {
-            Kind::Text(text) => {
-                state.new_spoiler(bot_id, Content::Text(text));
-            }
-            Kind::Audio(audio, caption) => {
-                state.new_spoiler(bot_id, Content::Audio(audio, caption));
-            }
-            Kind::Document(document, caption) => {
-                state.new_spoiler(bot_id, Content::Document(document, caption))
-            }
-            Kind::Dice(dice) => {
-                state.new_spoiler(bot_id, Content::Dice(dice));
-            }
-            Kind::Photo(photo, caption, media_group_id) => {
-                state.new_spoiler(bot_id, Content::Photo(photo, caption, media_group_id));
-            }
-            Kind::Sticker(sticker) => {
-                state.new_spoiler(bot_id, Content::Sticker(sticker));
-            }
-            Kind::Video(video, caption, media_group_id) => {
-                state.new_spoiler(bot_id, Content::Video(video, caption, media_group_id));
-            }
-            Kind::Voice(voice, caption) => {
-                state.new_spoiler(bot_id, Content::Voice(voice, caption));
-            }
-            Kind::VideoNote(video_note) => {
-                state.new_spoiler(bot_id, Content::VideoNote(video_note));
-            }
-            Kind::Contact(contact) => {
-                state.new_spoiler(bot_id, Content::Contact(contact));
-            }
-            Kind::Location(location) => {
-                state.new_spoiler(bot_id, Content::Location(location));
-            }
-            Kind::Animation(animation, caption) => {
-                state.new_spoiler(bot_id, Content::Animation(animation, caption));
+    if let Some(spoiled) = message.reply_to_message() {
+        let author = spoiled
+            .from
+            .as_ref()
+            .map_or_else(String::new, |user| user.first_name.clone());
+
+        if let Some(content) = Content::from_message(spoiled) {
+            spoil_message(
+                &bot,
+                state,
+                message.chat.id,
+                spoiled.id,
+                &author,
+                content,
+                Arguments::parse(&command.text),
+            )
+            .await;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces a message in a group by a spoiler.
+///
+/// The content of the message gets stored as a new spoiler, a spoiler with a button to reveal it
+/// is posted and the original message is deleted. If the spoiler cannot be posted or the original
+/// message cannot be deleted, the original message stays, the posted spoiler is deleted again and
+/// the stored spoiler is discarded.
+///
+/// The group's [settings] decide about the spoiler's defaults: when it expires, how it is
+/// revealed and how its author is shown. Explicit [`Arguments`] take precedence.
+///
+/// If a [forward chat] is configured and the group shows authors, the original message gets
+/// forwarded there first so the spoiler can later be revealed with its author attribution intact.
+///
+/// # Returns
+/// `true` if the spoiler was posted and the original message deleted.
+///
+/// [settings]: crate::state::chat_settings::ChatSettings
+/// [forward chat]: crate::config::Config#structfield.forward_chat
+pub(crate) async fn spoil_message(
+    bot: &Bot,
+    state: Arc<State>,
+    chat_id: ChatId,
+    message_id: MessageId,
+    author: &str,
+    content: Content,
+    arguments: Arguments,
+) -> bool {
+    let settings = state.get_chat_settings(&chat_id);
+    let attribution = settings.attribution(author);
+    let title = arguments.title.clone().or_else(|| attribution.clone());
+    let expires_in = arguments.expires_in.unwrap_or(settings.default_expiration);
+    let mut paged = callback::is_paged(&content);
+    let id = state.create_spoiler(title, content, Some(expires_in), None);
+    state.set_spoiler_policy(&id, settings.reveal);
+
+    // keep a copy of the original message around so its author stays visible
+    if let Some(forward_chat) = state.config.forward_chat.filter(|_| settings.show_author) {
+        match bot.forward_message(forward_chat, chat_id, message_id).await {
+            Ok(forwarded) => {
+                state.set_spoiler_origin(
+                    &id,
+                    Origin {
+                        chat: forward_chat,
+                        message: forwarded.id,
+                    },
+                );
+                // attributed spoilers are revealed in a private chat
+                paged = false;
             }
-            _ => {
-                return;
+            Err(e) => {
+                dbg!(e);
             }
         }
+    }
 
-        // post it in the group
-        let mut spoiler_id = String::from(INLINE_QUERY_SEPARATOR);
-
-        let title = format!(
-            "Bad message from {}:",
-            &context
-                .reply_to
-                .as_ref()
-                .unwrap()
-                .from
-                .as_ref()
-                .unwrap()
-                .first_name
-        );
-        spoiler_id.push_str(&*state.set_spoiler_title_and_expiration(bot_id, title.clone(), None));
-        let reply_markup: Markup = &[&[Button::new(
-            SHOW_SPOILER,
-            ButtonKind::CallbackData(&spoiler_id),
-        )]];
-
-        let spoiler = format!(
-            "<b>Spoiler!</b>{}{}",
-            format!("\n<code>{}</code>", &context.text.value),
-            format!("\n\n{}", &title)
-        );
-        if let Err(e) = context
-            .bot
-            .send_message(context.chat.id, parameters::Text::with_html(&spoiler))
-            .reply_markup(reply_markup)
-            .call()
-            .await
-        {
+    // post the spoiler in the group
+    let heading = match settings.reveal {
+        RevealPolicy::Taps(1) => "<b>Spoiler!</b>".to_string(),
+        policy => format!("<b>{}!</b>", policy.spoiler_name()),
+    };
+    let buttons = match callback::spoiler_buttons(settings.reveal, &id, paged, &state.config.secret)
+    {
+        Some(buttons) => buttons,
+        None => {
+            state.discard_spoiler(&id);
+            return false;
+        }
+    };
+    let row: Vec<InlineKeyboardButton> = buttons
+        .into_iter()
+        .map(|(label, data)| InlineKeyboardButton::callback(label, data))
+        .collect();
+    let reply_markup = InlineKeyboardMarkup::new([row]);
+
+    let title = match arguments.title {
+        Some(title) => format!("\n<code>{}</code>", html::escape(&title)),
+        None => "".to_string(),
+    };
+    let reason = match arguments.reason {
+        Some(reason) => format!("\n<i>Reason: {}</i>", html::escape(&reason)),
+        None => "".to_string(),
+    };
+    let attribution = match attribution {
+        Some(attribution) => format!("\n\n{}", html::escape(&attribution)),
+        None => "".to_string(),
+    };
+    let spoiler = format!(
+        "{}{}{}{}\n(Expires at {})",
+        heading,
+        title,
+        reason,
+        attribution,
+        util::expires_at(expires_in)
+    );
+    let posted = match bot
+        .send_message(chat_id, spoiler)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(reply_markup)
+        .await
+    {
+        Ok(posted) => posted,
+        Err(e) => {
             dbg!(e);
+            state.discard_spoiler(&id);
+            return false;
         }
+    };
 
-        // and finally delete the message in reply to
-        if let Err(e) = context
-            .bot
-            .delete_message(context.chat.id, message.id)
-            .call()
-            .await
-        {
+    // and finally delete the original message, or take the spoiler back if that is not possible
+    if let Err(e) = bot.delete_message(chat_id, message_id).await {
+        dbg!(e);
+        if let Err(e) = bot.delete_message(chat_id, posted.id).await {
             dbg!(e);
         }
+        state.discard_spoiler(&id);
+        return false;
+    }
+    true
+}
+
+/// The optional arguments of the `/spoiler` command.
+///
+/// The command is used as follows, where each part may be omitted:
+/// ```text
+/// /spoiler title for the spoiler:::reason shown to the group /2d
+/// ```
+/// The expiration suffix is parsed the same way as in [`util::parse_duration`].
+#[derive(Default)]
+pub(crate) struct Arguments {
+    /// The title to store the spoiler with.
+    pub(crate) title: Option<String>,
+    /// Why the message got spoiled.
+    pub(crate) reason: Option<String>,
+    /// The amount of time until the spoiler expires.
+    pub(crate) expires_in: Option<Duration>,
+}
+
+impl Arguments {
+    /// Parses the arguments from the text following the command.
+    pub(crate) fn parse(text: &String) -> Self {
+        let expires_in = util::parse_duration(text);
+        let text = util::strip_expiration_suffix(text);
+        let mut parts = text.splitn(2, SPOILER_TITLE_SEPARATOR).map(str::trim);
+
+        let non_empty = |part: Option<&str>| part.filter(|p| !p.is_empty()).map(str::to_string);
+        Arguments {
+            title: non_empty(parts.next()),
+            reason: non_empty(parts.next()),
+            expires_in,
+        }
     }
 }
 
 /// Returns `true` if the bot is allowed to delete messages from other users
 /// in the group where the request came from.
-async fn can_delete_messages(context: Arc<Command<Text>>) -> bool {
-    match context
-        .bot
-        .get_chat_administrators(context.chat.id)
-        .call()
-        .await
-    {
-        Ok(users) => {
-            for member in users {
-                if member
-                    .user
-                    .id
-                    .eq(&context.bot.get_me().call().await.unwrap().user.id)
-                {
-                    match member.status {
-                        Status::Administrator {
-                            can_delete_messages,
-                            ..
-                        } => {
-                            if !can_delete_messages {
-                                if let Err(e) = context
-                                    .send_message_in_reply(NO_DELETE_PERMISSION)
-                                    .call()
-                                    .await
-                                {
-                                    dbg!(e);
-                                }
-                            }
-                            return can_delete_messages;
-                        }
-                        _ => {
-                            if let Err(e) = context.send_message_in_reply(NOT_AN_ADMIN).call().await
-                            {
-                                dbg!(e);
-                            }
-                        }
-                    }
-                }
+async fn can_delete_messages(
+    bot: &Bot,
+    message: &Message,
+    admins: &[ChatMember],
+    bot_id: UserId,
+) -> bool {
+    match admins.iter().find(|member| member.user.id == bot_id) {
+        Some(ChatMember {
+            kind:
+                ChatMemberKind::Administrator(Administrator {
+                    can_delete_messages,
+                    ..
+                }),
+            ..
+        }) => {
+            if !can_delete_messages {
+                util::reply(bot, message, NO_DELETE_PERMISSION).await;
             }
+            *can_delete_messages
+        }
+        _ => {
+            util::reply(bot, message, NOT_AN_ADMIN).await;
+            false
         }
-        Err(err) => panic!("{}", err),
     }
-    false
+}
+
+/// Returns `true` if the group's [`ModerationPolicy`] allows the user to spoiler the message
+/// in reply to.
+///
+/// [`ModerationPolicy`]: crate::state::chat_settings::ModerationPolicy
+async fn may_spoiler(bot: &Bot, message: &Message, state: &State, admins: &[ChatMember]) -> bool {
+    let user_id = match message.from.as_ref() {
+        Some(user) => user.id,
+        None => return false,
+    };
+    let is_own_message = message
+        .reply_to_message()
+        .and_then(|message| message.from.as_ref())
+        .is_some_and(|author| author.id == user_id);
+
+    let allowed = state
+        .get_chat_settings(&message.chat.id)
+        .moderation
+        .allows(util::is_admin(admins, user_id), is_own_message);
+    if !allowed {
+        util::reply(bot, message, NOT_ALLOWED_TO_SPOILER).await;
+    }
+    allowed
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        bot::{
+            mock_api::{self, MockApi},
+            payload::CallbackPayload,
+            recorded,
+        },
+        config::Config,
+    };
+
+    /// The group the recorded messages are sent to.
+    const GROUP: i64 = -1001234567890;
+
+    /// Replies `/spoiler` with the given arguments to a message in a group where the bot may
+    /// delete messages or not.
+    async fn spoiler_reply(
+        api: &MockApi,
+        arguments: &str,
+        can_delete_messages: bool,
+        state: &Arc<State>,
+    ) {
+        api.respond(
+            "getChatAdministrators",
+            mock_api::ok(json!([
+                recorded::creator(),
+                recorded::administrator(recorded::bot_user(), can_delete_messages),
+            ])),
+        );
+        let message = recorded::group_reply(
+            json!({
+                "text": format!("/spoiler {}", arguments),
+                "entities": [{ "offset": 0, "length": 8, "type": "bot_command" }],
+            }),
+            "Snape kills Dumbledore",
+        );
+        let command = Command::parse(&message).unwrap();
+
+        spoiler(api.bot(), message, command, state.clone())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn replaces_the_message_by_a_spoiler() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+
+        spoiler_reply(&api, "Harry Potter:::Finale", true, &state).await;
+
+        let posted = api.call("sendMessage");
+        assert_eq!(posted["chat_id"], GROUP);
+        assert_eq!(posted["parse_mode"], "HTML");
+        let text = posted["text"].as_str().unwrap();
+        assert!(
+            text.starts_with("<b>Spoiler!</b>\n<code>Harry Potter</code>\n<i>Reason: Finale</i>")
+        );
+
+        let data = posted["reply_markup"]["inline_keyboard"][0][0]["callback_data"]
+            .as_str()
+            .unwrap();
+        let spoiler_id = match CallbackPayload::decode(data, &state.config.secret) {
+            Ok(CallbackPayload::Reveal { spoiler_id, .. }) => spoiler_id,
+            payload => panic!("not a spoiler button: {:?}", payload),
+        };
+        let spoiler = state.get_spoiler(&spoiler_id).unwrap();
+        assert!(
+            matches!(&spoiler.content, Content::Text(text) if text == "Snape kills Dumbledore")
+        );
+
+        let deleted = api.call("deleteMessage");
+        assert_eq!(deleted["chat_id"], GROUP);
+        assert_eq!(deleted["message_id"], 1364);
+    }
+
+    #[tokio::test]
+    async fn needs_permission_to_delete_messages() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+
+        spoiler_reply(&api, "", false, &state).await;
+
+        let reply = api.call("sendMessage");
+        assert_eq!(reply["text"], NO_DELETE_PERMISSION);
+        assert_eq!(reply["reply_parameters"]["message_id"], 1365);
+        assert!(api.calls("deleteMessage").is_empty());
+        assert_eq!(state.spoiler_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn takes_the_spoiler_back_if_the_message_stays() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        api.respond(
+            "deleteMessage",
+            mock_api::error("Bad Request: message can't be deleted"),
+        );
+
+        spoiler_reply(&api, "", true, &state).await;
+
+        let posted = api.call("sendMessage");
+        let deleted: Vec<_> = api
+            .calls("deleteMessage")
+            .iter()
+            .map(|call| call["message_id"].clone())
+            .collect();
+        // the posted spoiler answered the second request
+        assert_eq!(deleted, [json!(1364), json!(2001)]);
+        assert_eq!(
+            api.methods(),
+            [
+                "getchatadministrators",
+                "sendmessage",
+                "deletemessage",
+                "deletemessage"
+            ]
+        );
+        assert_eq!(posted["chat_id"], GROUP);
+        assert_eq!(state.spoiler_count(), 0);
+    }
 }
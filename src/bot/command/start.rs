@@ -1,217 +1,315 @@
 //! Handles the `/start` command.
 use std::sync::Arc;
 
-use tbot::{
-    contexts::{fields::Context, Command, Text},
-    types::{
-        input_file::{Animation, Audio, Document, Photo, Sticker, Video, VideoNote, Voice},
-        parameters,
-    },
+use teloxide::{
+    prelude::*,
+    types::{DiceEmoji, InputFile},
 };
 
 use crate::{
-    state::{spoiler::Content, State},
-    strings::{bot_replies::PREPARING_A_SPOILER, CREATE_CUSTOM_SPOILER, INLINE_QUERY_SEPARATOR},
-    util::is_spoiler_id,
+    bot::command::Command,
+    state::{
+        spoiler::{Content, Spoiler},
+        token::{TokenError, TokenPurpose},
+        State,
+    },
+    strings::{
+        bot_replies::{
+            type_to_show_spoiler, EXPIRED_LINK, INVALID_LINK, PREPARING_A_SPOILER, REUSED_LINK,
+            SPOILER_NOT_FOUND,
+        },
+        CREATE_CUSTOM_SPOILER, INLINE_QUERY_SEPARATOR, WORD_CONFIRMATION_PREFIX,
+    },
 };
 
 /// Handle the `/start` command sent from a private chat.
 ///
 /// If the start parameter is empty or equals `CREATE_CUSTOM_SPOILER`, the bot will instruct the
-/// user to create a spoiler. Otherwise, it will send the requested spoiler (by the supplied spoiler
-/// id) to the user.
-pub(crate) async fn start_from_pm(context: Arc<Command<Text>>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
+/// user to create a spoiler. Otherwise, the start parameter carries a [token] for a spoiler, which
+/// is rejected if it was not issued to the user, has expired or has been used before.
+///
+/// If the parameter starts with `WORD_CONFIRMATION_PREFIX`, the bot asks the user to type the
+/// confirmation word first. Otherwise, it will send the requested spoiler to the user.
+///
+/// [token]: crate::state::token
+pub(crate) async fn start_from_pm(
+    bot: Bot,
+    message: Message,
+    command: Command,
+    state: Arc<State>,
+) -> ResponseResult<()> {
+    let user_id = match &message.from {
+        Some(user) => user.id,
+        None => return Ok(()),
+    };
 
-    if context.text.value.is_empty() || context.text.value.eq(CREATE_CUSTOM_SPOILER) {
+    if command.text.is_empty() || command.text.eq(CREATE_CUSTOM_SPOILER) {
         // Create a new spoiler
         let _status = state.set_waiting_for_spoiler(user_id);
 
-        if let Err(e) = context
-            .bot
-            .send_message(user_id, PREPARING_A_SPOILER)
-            .call()
-            .await
-        {
+        if let Err(e) = bot.send_message(user_id, PREPARING_A_SPOILER).await {
             dbg!(e.to_string());
         }
     } else {
-        // Send an already created spoiler
-        send_spoiler(context.clone(), state.clone()).await;
+        let (purpose, token) = match command.text.strip_prefix(WORD_CONFIRMATION_PREFIX) {
+            Some(token) => (TokenPurpose::TypeWord, token),
+            None => (
+                TokenPurpose::Reveal,
+                command.text.trim_start_matches(INLINE_QUERY_SEPARATOR),
+            ),
+        };
+        let spoiler_id = match state.check_token(purpose, token, user_id) {
+            Ok(spoiler_id) => spoiler_id,
+            Err(error) => {
+                let reply = match error {
+                    TokenError::Forged => INVALID_LINK,
+                    TokenError::Expired => EXPIRED_LINK,
+                    TokenError::Reused => REUSED_LINK,
+                };
+                if let Err(e) = bot.send_message(user_id, reply).await {
+                    dbg!(e);
+                }
+                return Ok(());
+            }
+        };
+
+        if purpose == TokenPurpose::TypeWord {
+            // Ask for the confirmation word before sending the spoiler
+            ask_for_confirmation_word(&bot, user_id, &state, &spoiler_id).await;
+        } else if let Some(spoiler) = state.get_spoiler(&spoiler_id) {
+            // Send an already created spoiler
+            send_spoiler(&bot, user_id, spoiler).await;
+        }
     }
+    Ok(())
 }
 
-/// Send the requested spoiler to the user
-async fn send_spoiler(context: Arc<Command<Text>>, state: Arc<State>) {
-    let spoiler_id = if is_spoiler_id(&context.text.value) {
-        context
-            .text
-            .value
-            .clone()
-            .split(INLINE_QUERY_SEPARATOR)
-            .collect::<String>()
-    } else {
-        context.text.value.clone()
+/// Asks the user to type the confirmation word in order to see the given spoiler.
+///
+/// The answer is checked once the user sends it, see [`text`](crate::bot::spoiler_creation::text).
+async fn ask_for_confirmation_word(bot: &Bot, user_id: UserId, state: &State, spoiler_id: &str) {
+    let reply = match state.get_spoiler_title(spoiler_id) {
+        Some(title) => {
+            state.await_confirmation_word(user_id, spoiler_id.to_string());
+            type_to_show_spoiler(&title)
+        }
+        None => SPOILER_NOT_FOUND.to_string(),
     };
-    if let Some(spoiler) = state.get_spoiler(&spoiler_id) {
-        let user_id = context.from.as_ref().unwrap().id;
-
-        match spoiler.content {
-            Content::Animation(animation, caption) => {
-                if let Err(e) = context
-                    .bot()
-                    .send_animation(
-                        user_id.to_owned(),
-                        Animation::with_id(animation.file_id.as_ref())
-                            .caption(parameters::Text::from(&caption.value)),
-                    )
-                    .call()
-                    .await
-                {
-                    dbg!(e);
-                }
-            }
-            Content::Audio(audio, caption) => {
-                if let Err(e) = context
-                    .bot()
-                    .send_audio(
-                        user_id.to_owned(),
-                        Audio::with_id(audio.file_id.as_ref()).caption(&caption.value),
-                    )
-                    .call()
-                    .await
-                {
-                    dbg!(e);
-                }
-            }
-            Content::Contact(contact) => {
-                if let Err(e) = context
-                    .bot()
-                    .send_contact(
-                        user_id.to_owned(),
-                        &contact.phone_number,
-                        &contact.first_name,
-                    )
-                    .call()
-                    .await
-                {
-                    dbg!(e);
-                }
-            }
-            Content::Dice(_) => {}
-            Content::Document(document, caption) => {
-                if let Err(e) = context
-                    .bot()
-                    .send_document(
-                        user_id.to_owned(),
-                        Document::with_id(document.file_id.as_ref()).caption(&caption.value),
-                    )
-                    .call()
-                    .await
-                {
-                    dbg!(e);
-                }
-            }
-            Content::Location(location) => {
-                if let Err(e) = context
-                    .bot()
-                    .send_location(user_id.to_owned(), (location.latitude, location.longitude))
-                    .call()
-                    .await
-                {
-                    dbg!(e);
-                }
-            }
-            Content::Photo(photos, caption, _media_group_id) => {
-                let photo = photos
-                    .iter()
-                    .max_by(|a, b| a.width.cmp(&b.width))
-                    .unwrap_or(photos.first().unwrap());
-
-                if let Err(e) = context
-                    .bot()
-                    .send_photo(
-                        user_id.to_owned(),
-                        Photo::with_id(photo.file_id.as_ref()).caption(&caption.value),
-                    )
-                    .call()
-                    .await
-                {
-                    dbg!(e);
-                }
-            }
-            Content::Sticker(sticker) => {
-                if let Err(e) = context
-                    .bot()
-                    .send_sticker(
-                        user_id.to_owned(),
-                        Sticker::with_id(sticker.file_id.as_ref()),
-                    )
-                    .call()
-                    .await
-                {
-                    dbg!(e);
-                }
-            }
-            Content::Text(text) => {
-                if let Err(e) = context
-                    .bot()
-                    .send_message(user_id.to_owned(), &text.value)
-                    .call()
-                    .await
-                {
-                    dbg!(e);
-                }
-            }
-            Content::String(text) => {
-                if let Err(e) = context
-                    .bot()
-                    .send_message(user_id.to_owned(), &text)
-                    .call()
-                    .await
-                {
-                    dbg!(e);
-                }
-            }
-            Content::Video(video, caption, _media_group_id) => {
-                if let Err(e) = context
-                    .bot()
-                    .send_video(
-                        user_id.to_owned(),
-                        Video::with_id(video.file_id.as_ref()).caption(&caption.value),
-                    )
-                    .call()
-                    .await
-                {
-                    dbg!(e);
-                }
-            }
-            Content::VideoNote(video_note) => {
-                if let Err(e) = context
-                    .bot()
-                    .send_video_note(
-                        user_id.to_owned(),
-                        VideoNote::with_id(video_note.file_id.as_ref()),
-                    )
-                    .call()
-                    .await
-                {
-                    dbg!(e);
-                }
-            }
-            Content::Voice(voice, caption) => {
-                if let Err(e) = context
-                    .bot()
-                    .send_voice(
-                        user_id.to_owned(),
-                        Voice::with_id(voice.file_id.as_ref()).caption(&caption.value),
-                    )
-                    .call()
-                    .await
-                {
-                    dbg!(e);
-                }
+
+    if let Err(e) = bot.send_message(user_id, reply).await {
+        dbg!(e);
+    }
+}
+
+/// Send the requested spoiler to the user
+///
+/// Spoilers created from someone else's message are forwarded, if possible, so that the user can
+/// see who originally sent it. Animations, photos and videos are sent blurred, so they are not
+/// seen at a glance.
+pub(crate) async fn send_spoiler(bot: &Bot, user_id: UserId, spoiler: Spoiler) {
+    // Prefer forwarding the original message, fall back to the stored content once it's gone
+    if let Some(origin) = &spoiler.origin {
+        match bot
+            .forward_message(user_id, origin.chat, origin.message)
+            .await
+        {
+            Ok(_) => return,
+            Err(e) => {
+                dbg!(e);
             }
         }
     }
+
+    let sent = match spoiler.content {
+        Content::Animation(animation, caption) => bot
+            .send_animation(user_id, InputFile::file_id(animation.file.id))
+            .caption(caption)
+            .has_spoiler(true)
+            .await
+            .map(drop),
+        Content::Audio(audio, caption) => bot
+            .send_audio(user_id, InputFile::file_id(audio.file.id))
+            .caption(caption)
+            .await
+            .map(drop),
+        Content::Contact(contact) => bot
+            .send_contact(user_id, contact.phone_number, contact.first_name)
+            .await
+            .map(drop),
+        // sending a dice would roll it again, so tell the value it showed instead
+        Content::Dice(dice) => bot
+            .send_message(
+                user_id,
+                format!("{} {}", dice_emoji(dice.emoji), dice.value),
+            )
+            .await
+            .map(drop),
+        Content::Document(document, caption) => bot
+            .send_document(user_id, InputFile::file_id(document.file.id))
+            .caption(caption)
+            .await
+            .map(drop),
+        Content::Location(location) => bot
+            .send_location(user_id, location.latitude, location.longitude)
+            .await
+            .map(drop),
+        Content::Photo(photos, caption, _media_group_id) => {
+            let photo = photos
+                .iter()
+                .max_by(|a, b| a.width.cmp(&b.width))
+                .unwrap_or(photos.first().unwrap());
+
+            bot.send_photo(user_id, InputFile::file_id(photo.file.id.clone()))
+                .caption(caption)
+                .has_spoiler(true)
+                .await
+                .map(drop)
+        }
+        Content::Sticker(sticker) => bot
+            .send_sticker(user_id, InputFile::file_id(sticker.file.id))
+            .await
+            .map(drop),
+        Content::Text(text) | Content::String(text) => {
+            bot.send_message(user_id, text).await.map(drop)
+        }
+        Content::Video(video, caption, _media_group_id) => bot
+            .send_video(user_id, InputFile::file_id(video.file.id))
+            .caption(caption)
+            .has_spoiler(true)
+            .await
+            .map(drop),
+        Content::VideoNote(video_note) => bot
+            .send_video_note(user_id, InputFile::file_id(video_note.file.id))
+            .await
+            .map(drop),
+        Content::Voice(voice, caption) => bot
+            .send_voice(user_id, InputFile::file_id(voice.file.id))
+            .caption(caption)
+            .await
+            .map(drop),
+    };
+    if let Err(e) = sent {
+        dbg!(e);
+    }
+}
+
+/// Returns the emoji the dice is shown as.
+fn dice_emoji(emoji: DiceEmoji) -> &'static str {
+    match emoji {
+        DiceEmoji::Dice => "🎲",
+        DiceEmoji::Darts => "🎯",
+        DiceEmoji::Bowling => "🎳",
+        DiceEmoji::Basketball => "🏀",
+        DiceEmoji::Football => "⚽",
+        DiceEmoji::SlotMachine => "🎰",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bot::{mock_api::MockApi, recorded},
+        config::Config,
+    };
+
+    /// Sends `/start` with the given parameter to the bot and returns the requests it made.
+    async fn start(parameter: &str, state: Arc<State>) -> MockApi {
+        let api = MockApi::start().await;
+        let message = recorded::private_message(&format!("/start {}", parameter));
+        let command = Command::parse(&message).unwrap();
+
+        start_from_pm(api.bot(), message, command, state)
+            .await
+            .unwrap();
+        api
+    }
+
+    #[tokio::test]
+    async fn deep_links_start_the_spoiler_creation() {
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+
+        for parameter in ["", CREATE_CUSTOM_SPOILER] {
+            let api = start(parameter, state.clone()).await;
+
+            let reply = api.call("sendMessage");
+            assert_eq!(reply["chat_id"], recorded::USER_ID);
+            assert_eq!(reply["text"], PREPARING_A_SPOILER);
+            assert!(state
+                .cancel_spoiler_creation(&UserId(recorded::USER_ID))
+                .is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn deep_links_reveal_spoilers_once() {
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let user = UserId(recorded::USER_ID);
+        let spoiler_id = state.create_spoiler(
+            Some("Harry Potter".to_string()),
+            Content::Text("Snape kills Dumbledore".to_string()),
+            None,
+            None,
+        );
+        let parameter = format!(
+            "{}{}",
+            INLINE_QUERY_SEPARATOR,
+            state.issue_token(TokenPurpose::Reveal, &spoiler_id, user)
+        );
+
+        let api = start(&parameter, state.clone()).await;
+        assert_eq!(api.call("sendMessage")["text"], "Snape kills Dumbledore");
+
+        let api = start(&parameter, state.clone()).await;
+        assert_eq!(api.call("sendMessage")["text"], REUSED_LINK);
+    }
+
+    #[tokio::test]
+    async fn deep_links_ask_for_the_confirmation_word() {
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let spoiler_id = state.create_spoiler(
+            Some("Harry Potter".to_string()),
+            Content::Text("Snape kills Dumbledore".to_string()),
+            None,
+            None,
+        );
+        let parameter = format!(
+            "{}{}",
+            WORD_CONFIRMATION_PREFIX,
+            state.issue_token(
+                TokenPurpose::TypeWord,
+                &spoiler_id,
+                UserId(recorded::USER_ID)
+            )
+        );
+
+        let api = start(&parameter, state).await;
+
+        let reply = api.call("sendMessage");
+        assert_eq!(reply["text"], type_to_show_spoiler("Harry Potter"));
+    }
+
+    #[tokio::test]
+    async fn deep_links_of_other_users_are_rejected() {
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let spoiler_id = state.create_spoiler(
+            None,
+            Content::Text("Snape kills Dumbledore".to_string()),
+            None,
+            None,
+        );
+        let parameter = format!(
+            "{}{}",
+            INLINE_QUERY_SEPARATOR,
+            state.issue_token(TokenPurpose::Reveal, &spoiler_id, UserId(42))
+        );
+
+        let api = start(&parameter, state).await;
+
+        assert_eq!(api.call("sendMessage")["text"], INVALID_LINK);
+    }
 }
@@ -0,0 +1,29 @@
+//! Handles the `/stats` command.
+use std::sync::Arc;
+
+use teloxide::prelude::*;
+
+use crate::state::State;
+use crate::strings::bot_replies::{stats_text, ONLY_OPERATOR_CAN_SEE_STATS};
+use crate::util;
+
+/// Handle the `/stats` command sent from a private chat.
+///
+/// This will reply with the bot's [metrics], but only to the configured [operator].
+///
+/// [metrics]: crate::state::metrics::Metrics
+/// [operator]: crate::config::Config#structfield.operator
+pub(crate) async fn stats(bot: Bot, message: Message, state: Arc<State>) -> ResponseResult<()> {
+    let is_operator = match (&message.from, state.config.operator) {
+        (Some(user), Some(operator)) => user.id == operator,
+        _ => false,
+    };
+    let reply = if is_operator {
+        stats_text(&state.metrics, state.spoiler_count())
+    } else {
+        ONLY_OPERATOR_CAN_SEE_STATS.to_string()
+    };
+
+    util::reply(&bot, &message, &reply).await;
+    Ok(())
+}
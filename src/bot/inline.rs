@@ -3,189 +3,470 @@
 //! [tg doc]: https://core.telegram.org/bots/api#inline-mode
 use std::sync::Arc;
 
-use tbot::{
-    contexts::Inline,
-    types::{inline_query, input_message_content, keyboard::inline, parameters},
-};
-
 use crate::{
-    state::{spoiler::Content, State},
+    bot::{
+        callback,
+        query::{Mode, SpoilerQuery},
+    },
+    state::{
+        rate_limit::{Action, Subject},
+        reveal::RevealPolicy,
+        spoiler::{Content, Spoiler},
+        token::TokenPurpose,
+        State,
+    },
     strings::{
-        INLINE_QUERY_SEPARATOR, MAJOR_SPOILER_IDENTIFIER, SHOW_SPOILER, SPOILER_TITLE_SEPARATOR,
+        bot_replies::{invalid_query, query_feedback},
+        ADVANCED_SPOILER, CALLBACK_PAYLOAD_SEPARATOR, CREATE_CUSTOM_SPOILER,
+        INLINE_QUERY_SEPARATOR, NATIVE_SPOILER, NATIVE_SPOILER_DESCRIPTION, SLOW_DOWN_INLINE,
+        UNTITLED_SPOILER,
     },
     util,
 };
+use teloxide::{
+    prelude::*,
+    types::{
+        ChosenInlineResult, InlineKeyboardButton, InlineKeyboardMarkup, InlineQueryResult,
+        InlineQueryResultArticle, InlineQueryResultsButton, InlineQueryResultsButtonKind,
+        InputMessageContent, InputMessageContentText, ParseMode,
+    },
+    utils::html,
+};
+
+/// The reveal policies offered for spoilers created from an inline query, in the order they are
+/// listed.
+const INLINE_POLICIES: [RevealPolicy; 4] = [
+    RevealPolicy::Taps(1),
+    RevealPolicy::Taps(2),
+    RevealPolicy::Confirm,
+    RevealPolicy::TypeWord,
+];
+
+/// The maximum number of recent spoilers listed for an empty inline query.
+const RECENT_SPOILERS: usize = 20;
+
+/// Marks the [result id](result_id) of results using Telegram's own spoiler formatting.
+static NATIVE_RESULT_CODE: &str = "n";
 
 /// Handle [inline queries]
 ///
-/// Upon typing the text to be spoiled, the bot will display three options to create a spoiler:
+/// Upon typing the text to be spoiled, the bot will display these options to create a spoiler:
 /// - A *minor Spoiler*, where the message is hidden behind an [alert]
 ///   that requires a single tap to open.
 /// - A *major Spoiler*, where the message is hidden behind an [alert]
 ///   that requires two taps to open.
+/// - A *confirmed Spoiler*, where the tap needs to be confirmed with a second button.
+/// - A *hidden Spoiler*, that is only revealed after typing a confirmation word in a private chat.
 /// - An advanced spoiler, where the user can upload images,
 ///   videos etc. and optionally set a title for the spoiler
 ///
+/// An empty query lists the user's [recent spoilers](State::recent_spoilers) instead, so they can
+/// be posted again.
+///
 /// [inline queries]: https://core.telegram.org/bots/api#inline-mode
 /// [alert]: https://core.telegram.org/bots/api#answercallbackquery
-pub(crate) async fn inline(context: Arc<Inline>, state: Arc<State>) {
-    let spoiler_title = parse_spoiler_title(context.clone(), state.clone()).await;
-    let spoiler_id = parse_spoiler_id(context.clone(), state.clone()).await;
-    let expires_in = expires_in(&spoiler_id, state.clone());
-
-    // Minor spoiler
-    let minor_spoiler = &format!(
-        "<i>Minor spoiler!</i>{}{}",
-        format!("\n<code>{}</code>", &spoiler_title),
-        &expires_in,
-    );
-    let generated_minor_spoiler =
-        input_message_content::Text::new(parameters::Text::with_html(minor_spoiler));
-
-    let minor_spoiler =
-        inline_query::result::Article::new("Minor Spoiler", generated_minor_spoiler)
-            .description("Text, single tap")
-            .thumb(
-                inline_query::result::Thumb::new("https://i.imgur.com/csh5H5O.png")
-                    .width(512)
-                    .height(512),
-            );
-    let rid = util::random_id();
+pub(crate) async fn inline(bot: Bot, query: InlineQuery, state: Arc<State>) -> ResponseResult<()> {
+    if query.query.trim().is_empty() {
+        if let Err(e) = bot
+            .answer_inline_query(query.id.clone(), recent_results(query.from.id, &state))
+            .is_personal(true)
+            .cache_time(0)
+            .button(start_button(ADVANCED_SPOILER))
+            .await
+        {
+            dbg!(e.to_string());
+        }
+        return Ok(());
+    }
+
+    // every query that does not share an existing spoiler creates a new one
+    let spoiler_query = if util::is_spoiler_id(&query.query) {
+        None
+    } else {
+        match SpoilerQuery::parse(&query.query) {
+            Ok(spoiler_query) => Some(spoiler_query),
+            Err(e) => {
+                answer_without_results(&bot, &query, &invalid_query(&e)).await;
+                return Ok(());
+            }
+        }
+    };
+    if spoiler_query.is_some() && !state.allow(Action::Create, &[Subject::User(query.from.id)]) {
+        answer_without_results(&bot, &query, SLOW_DOWN_INLINE).await;
+        return Ok(());
+    }
 
-    let minor_button_kind = inline::ButtonKind::CallbackData(&spoiler_id);
-    let minor_spoiler_keyboard_markup: inline::Markup =
-        &[&[inline::Button::new(SHOW_SPOILER, minor_button_kind)]];
+    let spoiler_title = match &spoiler_query {
+        Some(spoiler_query) => spoiler_query.title.clone().unwrap_or_default(),
+        None => shared_spoiler_title(&query, &state),
+    };
+    let spoiler_id = match parse_spoiler_id(&query, spoiler_query.as_ref(), &state) {
+        Some(spoiler_id) => spoiler_id,
+        None => {
+            // the spoiler may not be shared by this user, only offer to create a new one
+            answer_without_results(&bot, &query, ADVANCED_SPOILER).await;
+            return Ok(());
+        }
+    };
+    let expires_in = expires_in(&spoiler_id, &state);
+    let spoiler = stored_spoiler(&spoiler_id, &state);
+    let content = spoiler.as_ref().map(|spoiler| spoiler.content.clone());
+    let summary = content
+        .as_ref()
+        .map_or_else(|| "Text".to_string(), Content::summary);
+    // tell users creating a new spoiler how their query was understood
+    let feedback = match (&spoiler_query, &spoiler) {
+        (Some(spoiler_query), Some(spoiler)) => format!(
+            "\n{}",
+            query_feedback(
+                &spoiler_query.content,
+                &util::expires_at(spoiler.expires_in),
+                spoiler_query.title.as_deref(),
+            )
+        ),
+        _ => String::new(),
+    };
+    let offers = |mode: Mode| spoiler_query.as_ref().is_none_or(|q| q.offers(mode));
+    let policies: Vec<RevealPolicy> = INLINE_POLICIES
+        .iter()
+        .copied()
+        .filter(|policy| offers(Mode::Reveal(*policy)))
+        .collect();
 
-    let minor_spoiler_result = inline_query::Result::new(&rid, minor_spoiler)
-        .reply_markup(inline::Keyboard::new(minor_spoiler_keyboard_markup));
+    let messages: Vec<String> = policies
+        .iter()
+        .map(|policy| spoiler_message(*policy, &spoiler_title, &expires_in))
+        .collect();
+    let descriptions: Vec<String> = policies
+        .iter()
+        .map(|policy| format!("{}, {}{}", summary, policy.description(), feedback))
+        .collect();
+    let paged = spoiler
+        .as_ref()
+        .is_some_and(|spoiler| spoiler.origin.is_none() && callback::is_paged(&spoiler.content));
+    let markups: Option<Vec<InlineKeyboardMarkup>> = policies
+        .iter()
+        .map(|policy| spoiler_markup(*policy, &spoiler_id, paged, &state.config.secret))
+        .collect();
+    let markups = match markups {
+        Some(markups) => markups,
+        None => {
+            answer_without_results(&bot, &query, ADVANCED_SPOILER).await;
+            return Ok(());
+        }
+    };
 
-    // Major spoiler
-    let major_spoiler = &*format!(
-        "<b>Major spoiler!</b>{}{}",
-        format!("\n<code>{}</code>", spoiler_title),
-        &expires_in
-    );
-    let generated_major_spoiler =
-        input_message_content::Text::new(parameters::Text::with_html(major_spoiler));
-
-    let major_spoiler =
-        inline_query::result::Article::new("Major Spoiler", generated_major_spoiler)
-            .description("Text, double tap")
-            .thumb(
-                inline_query::result::Thumb::new("https://i.imgur.com/3qqCZZk.png")
-                    .width(512)
-                    .height(512),
+    let kind = content.as_ref().map_or("text", Content::kind);
+    let mut results: Vec<InlineQueryResult> = policies
+        .iter()
+        .zip(messages)
+        .zip(descriptions)
+        .zip(markups)
+        .map(|(((policy, message), description), markup)| {
+            let content = InputMessageContent::Text(
+                InputMessageContentText::new(message).parse_mode(ParseMode::Html),
             );
+            let article = InlineQueryResultArticle::new(
+                result_id(Some(*policy), &spoiler_id),
+                policy.spoiler_name(),
+                content,
+            )
+            .description(description)
+            .reply_markup(markup);
 
-    let cd = format!("{}{}", MAJOR_SPOILER_IDENTIFIER, spoiler_id);
-    let major_button_kind = inline::ButtonKind::CallbackData(&cd);
-    let major_spoiler_keyboard_markup: inline::Markup = &[&[inline::Button::new(
-        "Double tap to show spoiler",
-        major_button_kind,
-    )]];
-
-    let rid: String = util::random_id();
-    let major_spoiler_result = inline_query::Result::new(&rid, major_spoiler)
-        .reply_markup(inline::Keyboard::new(major_spoiler_keyboard_markup));
+            InlineQueryResult::Article(with_thumbnail(article, &state, thumbnail(kind, *policy)))
+        })
+        .collect();
+    if offers(Mode::Native) {
+        results.extend(native_spoiler(&spoiler_id, &spoiler_title, &state));
+    }
 
-    if let Err(e) = context
-        .answer(&[minor_spoiler_result, major_spoiler_result])
+    if let Err(e) = bot
+        .answer_inline_query(query.id.clone(), results)
         .is_personal(true)
-        .switch_pm("Advanced spoiler (media etc.)…", "create_custom_spoiler")
-        .call()
+        .button(start_button(ADVANCED_SPOILER))
         .await
     {
         dbg!(e.to_string());
     }
+    Ok(())
 }
 
-/// Parse the (optional) spoiler title from an [inline query]
+/// Handle [chosen inline results], which Telegram only sends once inline feedback is enabled for
+/// the bot via [@BotFather].
 ///
-/// The spoiler title can either be
-/// - provided by the user in the inline query,
-/// - already defined while creating a custom spoiler or
-/// - not provided at all.
+/// The posted spoiler is attributed to the user, so it shows up among their recent spoilers, see
+/// [`State::claim_spoiler`].
 ///
-/// The bot user can provide a spoiler title by formatting the spoiler message as follows:
-/// ```
-/// spoiler title:::message to be spoiled
-/// ```
+/// [chosen inline results]: https://core.telegram.org/bots/inline#collecting-feedback
+/// [@BotFather]: https://t.me/botfather
+pub(crate) async fn chosen_inline_result(
+    result: ChosenInlineResult,
+    state: Arc<State>,
+) -> ResponseResult<()> {
+    if let Some((policy, spoiler_id)) = parse_result_id(&result.result_id) {
+        state.claim_spoiler(&spoiler_id, result.from.id, policy);
+    }
+    Ok(())
+}
+
+/// Returns a result for each of the user's [recent spoilers](State::recent_spoilers), which posts
+/// it again the way the user posted it last.
+fn recent_results(user: UserId, state: &State) -> Vec<InlineQueryResult> {
+    state
+        .recent_spoilers(user, RECENT_SPOILERS)
+        .into_iter()
+        .filter_map(|spoiler| {
+            let policy = spoiler.shared_policy.unwrap_or_default();
+            let title = spoiler.title.clone().unwrap_or_default();
+            let expires_at = util::expires_at(spoiler.remaining());
+            let message =
+                spoiler_message(policy, &title, &format!("\n\n(Expires at {})", expires_at));
+            let paged = callback::is_paged(&spoiler.content);
+            let markup = spoiler_markup(policy, &spoiler.id, paged, &state.config.secret)?;
+
+            let content = InputMessageContent::Text(
+                InputMessageContentText::new(message).parse_mode(ParseMode::Html),
+            );
+            let description = format!(
+                "{}, {}\nExpires at {}",
+                spoiler.content.summary(),
+                policy.description(),
+                expires_at
+            );
+            let title = if title.is_empty() {
+                UNTITLED_SPOILER.to_string()
+            } else {
+                title
+            };
+            let article =
+                InlineQueryResultArticle::new(result_id(Some(policy), &spoiler.id), title, content)
+                    .description(description)
+                    .reply_markup(markup);
+            let thumbnail = thumbnail(spoiler.content.kind(), policy);
+
+            Some(InlineQueryResult::Article(with_thumbnail(
+                article, state, thumbnail,
+            )))
+        })
+        .collect()
+}
+
+/// Returns the id of a result that posts the given spoiler with the given policy, or with
+/// Telegram's own spoiler formatting if there is none.
 ///
-/// [inline query]: https://core.telegram.org/bots/api#inline-mode
-async fn parse_spoiler_title(context: Arc<Inline>, state: Arc<State>) -> String {
-    let spoiler_id = if util::is_spoiler_id(&context.query) {
-        context
-            .query
-            .clone()
-            .rsplit(INLINE_QUERY_SEPARATOR)
-            .take(1)
-            .collect::<String>()
+/// Telegram hands it back once the result is [chosen](chosen_inline_result). The spoiler id may
+/// be given with or without the [`INLINE_QUERY_SEPARATOR`] prefix.
+fn result_id(policy: Option<RevealPolicy>, spoiler_id: &str) -> String {
+    let code = policy.map_or_else(|| NATIVE_RESULT_CODE.to_string(), RevealPolicy::code);
+    let spoiler_id = spoiler_id
+        .strip_prefix(INLINE_QUERY_SEPARATOR)
+        .unwrap_or(spoiler_id);
+
+    format!("{}{}{}", code, CALLBACK_PAYLOAD_SEPARATOR, spoiler_id)
+}
+
+/// Parses a [result id](result_id) into the policy the spoiler was posted with and its id.
+fn parse_result_id(result_id: &str) -> Option<(Option<RevealPolicy>, String)> {
+    let (code, spoiler_id) = result_id.split_once(CALLBACK_PAYLOAD_SEPARATOR)?;
+    if spoiler_id.is_empty() {
+        return None;
+    }
+    let policy = if code == NATIVE_RESULT_CODE {
+        None
     } else {
-        context.query.clone()
+        Some(RevealPolicy::from_code(code)?)
     };
 
-    match state.get_spoiler_title(&spoiler_id) {
-        Some(title) => title,
-        None => {
-            if context.query.contains(SPOILER_TITLE_SEPARATOR) {
-                context
-                    .query
-                    .split(SPOILER_TITLE_SEPARATOR)
-                    .collect::<Vec<&str>>()[0]
-                    .to_string()
-            } else {
-                "".to_string()
-            }
-        }
+    Some((policy, spoiler_id.to_string()))
+}
+
+/// Returns the name of the thumbnail of a result with content of the given kind that is revealed
+/// according to the policy.
+fn thumbnail(kind: &str, policy: RevealPolicy) -> &str {
+    match kind {
+        "text" if policy == RevealPolicy::Taps(1) => "minor",
+        "text" => "major",
+        kind => kind,
     }
 }
 
-/// Parses the spoiler content from an [inline query]
+/// Returns the message posted for a spoiler with the given title that is revealed according to
+/// the policy.
 ///
-/// [inline query]: https://core.telegram.org/bots/api#inline-mode
-async fn parse_spoiler_content(context: Arc<Inline>) -> String {
-    if context.query.contains(SPOILER_TITLE_SEPARATOR) {
-        context
-            .query
-            .split(SPOILER_TITLE_SEPARATOR)
-            .collect::<Vec<&str>>()[1]
-            .to_string()
+/// The message is formatted as HTML, so the title is escaped.
+fn spoiler_message(policy: RevealPolicy, title: &str, expires_in: &str) -> String {
+    let heading = match policy {
+        RevealPolicy::Taps(1) => format!("<i>{}!</i>", policy.spoiler_name()),
+        _ => format!("<b>{}!</b>", policy.spoiler_name()),
+    };
+    format!(
+        "{}\n<code>{}</code>{}",
+        heading,
+        html::escape(title),
+        expires_in
+    )
+}
+
+/// Returns the buttons below a spoiler that is revealed according to the policy.
+///
+/// A [paged](callback::is_paged) spoiler also gets a button showing its next page.
+///
+/// Returns `None` if the buttons cannot be created, see [`callback::spoiler_buttons`].
+fn spoiler_markup(
+    policy: RevealPolicy,
+    spoiler_id: &str,
+    paged: bool,
+    secret: &[u8],
+) -> Option<InlineKeyboardMarkup> {
+    let row: Vec<InlineKeyboardButton> =
+        callback::spoiler_buttons(policy, spoiler_id, paged, secret)?
+            .into_iter()
+            .map(|(label, data)| InlineKeyboardButton::callback(label, data))
+            .collect();
+    Some(InlineKeyboardMarkup::new([row]))
+}
+
+/// Returns a result that hides the text of the spoiler behind Telegram's own spoiler formatting,
+/// or `None` if the spoiler is not a text.
+///
+/// Unlike the other results, it does not need the bot to be revealed, so it is readable by anyone
+/// and does not expire.
+fn native_spoiler(spoiler_id: &String, title: &str, state: &State) -> Option<InlineQueryResult> {
+    let text = match stored_spoiler(spoiler_id, state)?.content {
+        Content::Text(text) | Content::String(text) => text,
+        _ => return None,
+    };
+
+    let heading = if title.is_empty() {
+        String::new()
     } else {
-        context.query.clone()
+        format!("<b>{}</b>\n", html::escape(title))
+    };
+    let message = format!(
+        "{}<tg-spoiler>{}</tg-spoiler>",
+        heading,
+        html::escape(&text)
+    );
+    let content = InputMessageContent::Text(
+        InputMessageContentText::new(message).parse_mode(ParseMode::Html),
+    );
+    let article =
+        InlineQueryResultArticle::new(result_id(None, spoiler_id), NATIVE_SPOILER, content)
+            .description(NATIVE_SPOILER_DESCRIPTION);
+
+    Some(InlineQueryResult::Article(with_thumbnail(
+        article, state, "native",
+    )))
+}
+
+/// Adds the [configured thumbnail](crate::config::Config::thumbnail) with the given name to the
+/// result, if there is one.
+fn with_thumbnail(
+    article: InlineQueryResultArticle,
+    state: &State,
+    name: &str,
+) -> InlineQueryResultArticle {
+    match state.config.thumbnail(name) {
+        Some(url) => article
+            .thumbnail_url(url)
+            .thumbnail_width(512)
+            .thumbnail_height(512),
+        None => article,
+    }
+}
+
+/// Answers the inline query without any results, only with a button to create a spoiler in a
+/// private chat that shows the given text.
+async fn answer_without_results(bot: &Bot, query: &InlineQuery, text: &str) {
+    if let Err(e) = bot
+        .answer_inline_query(query.id.clone(), Vec::new())
+        .is_personal(true)
+        .button(start_button(text))
+        .await
+    {
+        dbg!(e.to_string());
+    }
+}
+
+/// Returns a button shown above the results that opens a private chat to create a spoiler.
+fn start_button(text: &str) -> InlineQueryResultsButton {
+    InlineQueryResultsButton {
+        text: text.to_string(),
+        kind: InlineQueryResultsButtonKind::StartParameter(CREATE_CUSTOM_SPOILER.to_string()),
+    }
+}
+
+/// Returns the title of the spoiler shared by the [inline query], which was defined while
+/// creating a custom spoiler, or an empty title if there is none.
+///
+/// [inline query]: https://core.telegram.org/bots/api#inline-mode
+fn shared_spoiler_title(query: &InlineQuery, state: &State) -> String {
+    shared_spoiler_id(query, state)
+        .and_then(|id| state.get_spoiler_title(&id))
+        .unwrap_or_default()
+}
+
+/// Returns the id of the spoiler shared by the query, if it carries a [token] that was issued to
+/// the user.
+///
+/// [token]: crate::state::token
+fn shared_spoiler_id(query: &InlineQuery, state: &State) -> Option<String> {
+    let token = query.query.strip_prefix(INLINE_QUERY_SEPARATOR)?;
+    match state.check_token(TokenPurpose::Share, token, query.from.id) {
+        Ok(spoiler_id) => Some(spoiler_id),
+        Err(e) => {
+            dbg!(e);
+            None
+        }
     }
 }
 
 /// Parses the spoiler id from the given query.
 ///
-/// If a spoiler id is provided, this id will be returned.
-/// Otherwise, it creates a new spoiler from the user input and returns that id.
-async fn parse_spoiler_id(context: Arc<Inline>, state: Arc<State>) -> String {
-    if util::is_spoiler_id(&context.query) {
-        context.query.clone()
-    } else {
-        // Create a new spoiler from the inline query and return the spoiler id
-        let spoiler_content = parse_spoiler_content(context.clone()).await;
-        let spoiler_content = util::strip_expiration_suffix(&spoiler_content);
-        let spoiler_title = parse_spoiler_title(context.clone(), state.clone()).await;
-        let duration = util::parse_duration(&context.query);
-        dbg!(duration);
-
-        state.new_spoiler(context.from.id.clone(), Content::String(spoiler_content));
-
-        format!(
-            "{}{}",
-            INLINE_QUERY_SEPARATOR,
-            state.set_spoiler_title_and_expiration(
-                context.from.id.clone(),
-                spoiler_title.clone(),
-                duration
-            )
-        )
+/// If the query shares a spoiler, the id of that spoiler will be returned, or `None` if the user
+/// may not share it. Otherwise, it creates a new spoiler from the parsed query and returns that
+/// id.
+///
+/// Every keystroke creates a spoiler, most of which are never chosen, so they are only attributed
+/// to the user once [posted](chosen_inline_result). Until then, they neither show up among the
+/// user's recent spoilers nor count towards their
+/// [limit](crate::config::Config#structfield.max_spoilers_per_user).
+fn parse_spoiler_id(
+    query: &InlineQuery,
+    spoiler_query: Option<&SpoilerQuery>,
+    state: &State,
+) -> Option<String> {
+    match spoiler_query {
+        None => {
+            shared_spoiler_id(query, state).map(|id| format!("{}{}", INLINE_QUERY_SEPARATOR, id))
+        }
+        Some(spoiler_query) => {
+            // Create a new spoiler from the inline query and return the spoiler id
+            Some(format!(
+                "{}{}",
+                INLINE_QUERY_SEPARATOR,
+                state.create_spoiler(
+                    spoiler_query.title.clone(),
+                    Content::String(spoiler_query.content.clone()),
+                    spoiler_query.expires_in,
+                    None,
+                )
+            ))
+        }
     }
 }
 
 /// Returns a string representation of when the specified spoiler will expire.
-fn expires_in(spoiler_id: &String, state: Arc<State>) -> String {
+fn expires_in(spoiler_id: &String, state: &State) -> String {
+    match stored_spoiler(spoiler_id, state) {
+        None => "".to_string(),
+        Some(spoiler) => format!("\n\n(Expires at {})", util::expires_at(spoiler.remaining())),
+    }
+}
+
+/// Returns the spoiler with the given id, which may carry the [`INLINE_QUERY_SEPARATOR`] prefix.
+fn stored_spoiler(spoiler_id: &String, state: &State) -> Option<Spoiler> {
     let id = if util::is_spoiler_id(spoiler_id) {
         spoiler_id
             .clone()
@@ -195,8 +476,134 @@ fn expires_in(spoiler_id: &String, state: Arc<State>) -> String {
         spoiler_id.clone()
     };
 
-    match state.get_spoiler(&id) {
-        None => "".to_string(),
-        Some(spoiler) => format!("\n\n(Expires at {})", util::expires_at(spoiler.expires_in)),
+    state.get_spoiler(&id)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        bot::{mock_api::MockApi, payload::CallbackPayload, recorded},
+        config::Config,
+    };
+
+    /// Sends the inline query to the bot and returns its answer.
+    async fn answer(api: &MockApi, text: &str, state: &Arc<State>) -> Value {
+        let before = api.calls("answerInlineQuery").len();
+        inline(api.bot(), recorded::inline_query(text), state.clone())
+            .await
+            .unwrap();
+
+        let answers = api.calls("answerInlineQuery");
+        assert_eq!(answers.len(), before + 1);
+        answers[before].clone()
+    }
+
+    #[test]
+    fn titles_cannot_break_the_spoiler_message() {
+        let message = spoiler_message(RevealPolicy::Taps(1), "</code><b>&", "");
+
+        assert!(message.ends_with("<code>&lt;/code&gt;&lt;b&gt;&amp;</code>"));
+    }
+
+    #[test]
+    fn result_ids_tell_which_spoiler_was_posted_how() {
+        let spoiler_id = format!("{}abc123", INLINE_QUERY_SEPARATOR);
+        for policy in INLINE_POLICIES.iter().copied().map(Some).chain([None]) {
+            let result_id = result_id(policy, &spoiler_id);
+
+            assert!(result_id.len() <= 64);
+            assert_eq!(
+                parse_result_id(&result_id),
+                Some((policy, "abc123".to_string()))
+            );
+        }
+        assert_eq!(parse_result_id("abc123"), None);
+        assert_eq!(parse_result_id("x:abc123"), None);
+        assert_eq!(parse_result_id("1:"), None);
+    }
+
+    #[tokio::test]
+    async fn spoiler_queries_offer_each_way_of_revealing() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+
+        let answer = answer(&api, "Harry Potter ::: Snape kills Dumbledore", &state).await;
+
+        assert_eq!(answer["inline_query_id"], "1437238543719584301");
+        assert_eq!(answer["is_personal"], true);
+        assert_eq!(answer["button"]["text"], ADVANCED_SPOILER);
+        assert_eq!(answer["button"]["start_parameter"], CREATE_CUSTOM_SPOILER);
+        let results = answer["results"].as_array().unwrap();
+        assert_eq!(results.len(), INLINE_POLICIES.len() + 1);
+        for (result, policy) in results.iter().zip(INLINE_POLICIES) {
+            let (_, spoiler_id) = parse_result_id(result["id"].as_str().unwrap()).unwrap();
+            assert_eq!(result["title"], policy.spoiler_name());
+            let message = result["input_message_content"]["message_text"]
+                .as_str()
+                .unwrap();
+            assert!(message.contains("<code>Harry Potter</code>"), "{}", message);
+            assert_eq!(result["input_message_content"]["parse_mode"], "HTML");
+
+            let data = result["reply_markup"]["inline_keyboard"][0][0]["callback_data"]
+                .as_str()
+                .unwrap();
+            assert_eq!(
+                CallbackPayload::decode(data, &state.config.secret),
+                Ok(CallbackPayload::Reveal { policy, spoiler_id })
+            );
+        }
+
+        let native = &results[INLINE_POLICIES.len()];
+        assert_eq!(native["title"], NATIVE_SPOILER);
+        assert_eq!(
+            native["input_message_content"]["message_text"],
+            "<b>Harry Potter</b>\n<tg-spoiler>Snape kills Dumbledore</tg-spoiler>"
+        );
+        assert_eq!(native["reply_markup"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn invalid_queries_explain_what_is_wrong() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let text = "Snape kills Dumbledore [sparkly]";
+        let error = SpoilerQuery::parse(text).err().unwrap();
+
+        let answer = answer(&api, text, &state).await;
+
+        assert_eq!(answer["results"], serde_json::json!([]));
+        assert_eq!(answer["button"]["text"], invalid_query(&error));
+    }
+
+    #[tokio::test]
+    async fn empty_queries_list_the_spoilers_posted_before() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+
+        let before = answer(&api, "", &state).await;
+        let offered = answer(&api, "Harry Potter ::: Snape kills Dumbledore", &state).await;
+        let major = offered["results"][1]["id"].as_str().unwrap();
+        chosen_inline_result(recorded::chosen_inline_result(major), state.clone())
+            .await
+            .unwrap();
+        let after = answer(&api, "", &state).await;
+
+        assert_eq!(before["results"], serde_json::json!([]));
+        assert_eq!(after["cache_time"], 0);
+        assert_eq!(after["button"]["start_parameter"], CREATE_CUSTOM_SPOILER);
+        let recent = after["results"].as_array().unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0]["id"], major);
+        assert_eq!(recent[0]["title"], "Harry Potter");
+        assert_eq!(
+            recent[0]["reply_markup"],
+            offered["results"][1]["reply_markup"]
+        );
     }
 }
@@ -0,0 +1,260 @@
+//! A stand-in for the Bot API, for tests of the handlers.
+//!
+//! Handlers run with a [`Bot`] that talks to a local server instead. The server records every
+//! request and answers it the way the Bot API would, so tests can check the replies, answers and
+//! keyboards the bot sent.
+use std::{collections::HashMap, sync::Arc};
+
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use teloxide::Bot;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+use url::Url;
+
+/// A request the bot made.
+#[derive(Clone, Debug)]
+pub(crate) struct Request {
+    /// The name of the method in lowercase, e.g. `sendmessage`, since the Bot API ignores case.
+    pub(crate) method: String,
+    /// The parameters, whether they were sent as JSON or as a form, as when sending files.
+    pub(crate) params: Value,
+}
+
+/// The requests made so far and the responses tests asked for.
+#[derive(Default)]
+struct Recorder {
+    requests: Vec<Request>,
+    responses: HashMap<String, Value>,
+}
+
+/// A local server answering requests like the Bot API.
+#[derive(Clone)]
+pub(crate) struct MockApi {
+    url: Url,
+    recorder: Arc<Mutex<Recorder>>,
+}
+
+impl MockApi {
+    /// Starts the server on a free port.
+    pub(crate) async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let api = MockApi {
+            url,
+            recorder: Arc::default(),
+        };
+
+        let server = api.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(server.clone().serve(stream));
+            }
+        });
+        api
+    }
+
+    /// Returns a bot that talks to this server.
+    pub(crate) fn bot(&self) -> Bot {
+        Bot::new("1:token").set_api_url(self.url.clone())
+    }
+
+    /// Answers all further calls of the method with the given response, see [`ok`] and
+    /// [`error`].
+    pub(crate) fn respond(&self, method: &str, response: Value) {
+        self.recorder
+            .lock()
+            .responses
+            .insert(method.to_lowercase(), response);
+    }
+
+    /// Returns the names of the methods called so far, in order.
+    pub(crate) fn methods(&self) -> Vec<String> {
+        let recorder = self.recorder.lock();
+        recorder
+            .requests
+            .iter()
+            .map(|request| request.method.clone())
+            .collect()
+    }
+
+    /// Returns the parameters of every call of the method so far, in order.
+    pub(crate) fn calls(&self, method: &str) -> Vec<Value> {
+        let method = method.to_lowercase();
+        let recorder = self.recorder.lock();
+        recorder
+            .requests
+            .iter()
+            .filter(|request| request.method == method)
+            .map(|request| request.params.clone())
+            .collect()
+    }
+
+    /// Returns the parameters of the only call of the method.
+    ///
+    /// # Panics
+    /// If the method was called any other number of times.
+    pub(crate) fn call(&self, method: &str) -> Value {
+        let mut calls = self.calls(method);
+        assert_eq!(calls.len(), 1, "{} calls of {}", calls.len(), method);
+        calls.remove(0)
+    }
+
+    /// Answers the requests sent over the connection until it is closed.
+    async fn serve(self, stream: TcpStream) {
+        let mut stream = BufReader::new(stream);
+        while let Some((path, content_type, body)) = read_request(&mut stream).await {
+            let method = path.rsplit('/').next().unwrap_or_default().to_lowercase();
+            let body = String::from_utf8_lossy(&body);
+            let params = match content_type.split_once("boundary=") {
+                Some((_, boundary)) => form_fields(&body, boundary),
+                None => serde_json::from_str(&body).unwrap_or_default(),
+            };
+
+            let body = self.answer(method, params).to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            if stream
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+
+    /// Records the request and returns the response to it.
+    ///
+    /// Unless a test asked for another response, sent messages are returned for methods sending
+    /// or forwarding one, and `true` for all others.
+    fn answer(&self, method: String, params: Value) -> Value {
+        let mut recorder = self.recorder.lock();
+        let message_id = 2000 + recorder.requests.len();
+        let response = recorder.responses.get(&method).cloned();
+        let sends_message = method.starts_with("send") || method == "forwardmessage";
+        let result = if sends_message {
+            sent_message(message_id, &params)
+        } else {
+            json!(true)
+        };
+        recorder.requests.push(Request { method, params });
+
+        response.unwrap_or_else(|| ok(result))
+    }
+}
+
+/// Returns a successful response with the given result.
+pub(crate) fn ok(result: Value) -> Value {
+    json!({ "ok": true, "result": result })
+}
+
+/// Returns the response to a request the Bot API refused, with the given description.
+pub(crate) fn error(description: &str) -> Value {
+    json!({ "ok": false, "error_code": 400, "description": description })
+}
+
+/// Returns the message the bot sent with the given parameters.
+fn sent_message(message_id: usize, params: &Value) -> Value {
+    let chat_id = params["chat_id"].as_i64().unwrap_or_default();
+    let chat = if chat_id > 0 {
+        json!({ "id": chat_id, "type": "private", "first_name": "Lara" })
+    } else {
+        json!({ "id": chat_id, "type": "supergroup", "title": "Spoiler Club" })
+    };
+
+    json!({
+        "message_id": message_id,
+        "from": { "id": 1, "is_bot": true, "first_name": "Spoiler", "username": "spoilerowobot" },
+        "chat": chat,
+        "date": 1700000000,
+        "text": params["text"].as_str().unwrap_or_default(),
+    })
+}
+
+/// Returns the fields of a `multipart/form-data` body as an object.
+///
+/// Fields holding JSON, such as keyboards, numbers or flags, are parsed, all others are kept as
+/// text.
+fn form_fields(body: &str, boundary: &str) -> Value {
+    let mut fields = serde_json::Map::new();
+    for part in body.split(&format!("--{}", boundary.trim_matches('"'))) {
+        // e.g. "Content-Disposition: form-data; name=\"caption\"\r\n\r\nSnape kills Dumbledore"
+        let (headers, value) = match part.split_once("\r\n\r\n") {
+            Some(part) => part,
+            None => continue,
+        };
+        let name = match headers
+            .split("name=\"")
+            .nth(1)
+            .and_then(|name| name.split('"').next())
+        {
+            Some(name) => name,
+            None => continue,
+        };
+        let value = value.strip_suffix("\r\n").unwrap_or(value);
+        let value =
+            serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+        fields.insert(name.to_string(), value);
+    }
+    Value::Object(fields)
+}
+
+/// Reads the path, content type and body of the next request, or returns `None` once the
+/// connection is closed.
+async fn read_request(stream: &mut BufReader<TcpStream>) -> Option<(String, String, Vec<u8>)> {
+    // e.g. "POST /bot1:token/SendMessage HTTP/1.1"
+    let mut line = String::new();
+    stream.read_line(&mut line).await.ok()?;
+    let path = line.split_whitespace().nth(1)?.to_string();
+
+    let (mut content_type, mut length, mut chunked) = (String::new(), 0, false);
+    loop {
+        let mut header = String::new();
+        if stream.read_line(&mut header).await.ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_once(':')?;
+        match name.trim().to_lowercase().as_str() {
+            "content-type" => content_type = value.trim().to_string(),
+            "content-length" => length = value.trim().parse().ok()?,
+            "transfer-encoding" => chunked = value.to_lowercase().contains("chunked"),
+            _ => {}
+        }
+    }
+
+    if chunked {
+        return Some((path, content_type, read_chunks(stream).await?));
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await.ok()?;
+    Some((path, content_type, body))
+}
+
+/// Reads a body sent in chunks, as files are uploaded.
+async fn read_chunks(stream: &mut BufReader<TcpStream>) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let mut size = String::new();
+        stream.read_line(&mut size).await.ok()?;
+        let size = usize::from_str_radix(size.trim().split(';').next()?, 16).ok()?;
+
+        // each chunk, including the last empty one, ends with a line break
+        let mut chunk = vec![0; size + 2];
+        stream.read_exact(&mut chunk).await.ok()?;
+        if size == 0 {
+            return Some(body);
+        }
+        body.extend_from_slice(&chunk[..size]);
+    }
+}
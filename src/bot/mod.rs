@@ -1,5 +1,12 @@
 //! Implementation of the bot behaviour.
+pub(crate) mod auto_spoiler;
 pub(crate) mod callback;
 pub(crate) mod command;
 pub(crate) mod inline;
+#[cfg(test)]
+pub(crate) mod mock_api;
+pub(crate) mod payload;
+pub(crate) mod query;
+#[cfg(test)]
+pub(crate) mod recorded;
 pub(crate) mod spoiler_creation;
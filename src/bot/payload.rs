@@ -0,0 +1,360 @@
+//! The data carried by inline keyboard buttons.
+//!
+//! Telegram hands the [callback data] of a button back to the bot when it is tapped. Every button
+//! of the bot carries an encoded [`CallbackPayload`], so the [data callback handler] knows what
+//! the tap is about.
+//!
+//! Clients may send any callback data they like, so payloads are signed with an HMAC keyed with
+//! the bot's [secret]. A button can therefore only do what the bot created it for, e.g. nobody
+//! can reveal a spoiler they merely know the id of, or skip the confirmation it requires.
+//!
+//! [callback data]: https://core.telegram.org/bots/api#inlinekeyboardbutton
+//! [data callback handler]: crate::bot::callback::data_callback
+//! [secret]: crate::config::Config#structfield.secret
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    bot::command::settings::Setting,
+    state::reveal::RevealPolicy,
+    strings::{CALLBACK_PAYLOAD_SEPARATOR, CALLBACK_PAYLOAD_VERSION},
+};
+
+/// The maximum length of callback data in bytes, as [allowed by Telegram][tg docs].
+///
+/// [tg docs]: https://core.telegram.org/bots/api#inlinekeyboardbutton
+pub(crate) const MAX_CALLBACK_DATA_LENGTH: usize = 64;
+
+/// Separates signed payloads from [tokens](crate::state::token) signed with the same secret.
+static SIGNATURE_CONTEXT: &str = "callback";
+
+/// The number of bytes of the HMAC kept in callback data.
+const SIGNATURE_BYTES: usize = 16;
+
+/// What a button is about.
+///
+/// Payloads are encoded as the [version tag] followed by a short code for the kind of payload
+/// and its fields, all separated by [`CALLBACK_PAYLOAD_SEPARATOR`] and followed by the
+/// base64url encoded signature of everything before it:
+/// ```text
+/// v2:r:2:<spoiler id>:<signature>   reveal a spoiler that requires two taps
+/// v2:y:<spoiler id>:<signature>     confirm to reveal a spoiler
+/// v2:n:<spoiler id>:<signature>     show the next page of a long spoiler
+/// v2:b:<spoiler id>:<signature>     send a blurred copy of a media spoiler
+/// v2:s:mode:<signature>             change the reveal policy of a group
+/// ```
+///
+/// [version tag]: crate::strings::CALLBACK_PAYLOAD_VERSION
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub(crate) enum CallbackPayload {
+    /// A tap on the main button of a spoiler that is revealed according to the given policy.
+    Reveal {
+        /// How the spoiler is revealed.
+        policy: RevealPolicy,
+        /// The id of the spoiler, without the [`INLINE_QUERY_SEPARATOR`] prefix.
+        ///
+        /// [`INLINE_QUERY_SEPARATOR`]: crate::strings::INLINE_QUERY_SEPARATOR
+        spoiler_id: String,
+    },
+    /// A tap on the button confirming a [`RevealPolicy::Confirm`] spoiler.
+    Confirm {
+        /// The id of the spoiler, without the [`INLINE_QUERY_SEPARATOR`] prefix.
+        ///
+        /// [`INLINE_QUERY_SEPARATOR`]: crate::strings::INLINE_QUERY_SEPARATOR
+        spoiler_id: String,
+    },
+    /// A tap on the button showing the next page of a spoiler that is too long for one alert.
+    NextPage {
+        /// The id of the spoiler, without the [`INLINE_QUERY_SEPARATOR`] prefix.
+        ///
+        /// [`INLINE_QUERY_SEPARATOR`]: crate::strings::INLINE_QUERY_SEPARATOR
+        spoiler_id: String,
+    },
+    /// A tap on the button sending the creator of a photo, video or GIF spoiler a copy that is
+    /// blurred by Telegram itself.
+    Blur {
+        /// The id of the spoiler, without the [`INLINE_QUERY_SEPARATOR`] prefix.
+        ///
+        /// [`INLINE_QUERY_SEPARATOR`]: crate::strings::INLINE_QUERY_SEPARATOR
+        spoiler_id: String,
+    },
+    /// A tap on a button of the group settings menu.
+    ChangeSetting(Setting),
+}
+
+/// Why callback data could not be decoded.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(crate) enum PayloadError {
+    /// The button was created by an older version of the bot.
+    Outdated,
+    /// The data carries the current version tag, but its contents are not understood.
+    Unknown,
+    /// The signature does not match, because the data was not created by the bot or was signed
+    /// with another secret.
+    Forged,
+}
+
+impl CallbackPayload {
+    /// Encodes the payload as callback data signed with the given secret.
+    ///
+    /// Returns `None` if the data would be longer than [`MAX_CALLBACK_DATA_LENGTH`], which
+    /// Telegram would reject.
+    pub(crate) fn encode(&self, secret: &[u8]) -> Option<String> {
+        let fields = match self {
+            CallbackPayload::Reveal { policy, spoiler_id } => format!(
+                "r{}{}{}{}",
+                CALLBACK_PAYLOAD_SEPARATOR,
+                policy.code(),
+                CALLBACK_PAYLOAD_SEPARATOR,
+                spoiler_id
+            ),
+            CallbackPayload::Confirm { spoiler_id } => {
+                format!("y{}{}", CALLBACK_PAYLOAD_SEPARATOR, spoiler_id)
+            }
+            CallbackPayload::NextPage { spoiler_id } => {
+                format!("n{}{}", CALLBACK_PAYLOAD_SEPARATOR, spoiler_id)
+            }
+            CallbackPayload::Blur { spoiler_id } => {
+                format!("b{}{}", CALLBACK_PAYLOAD_SEPARATOR, spoiler_id)
+            }
+            CallbackPayload::ChangeSetting(setting) => {
+                format!("s{}{}", CALLBACK_PAYLOAD_SEPARATOR, setting.name())
+            }
+        };
+        let signed = format!(
+            "{}{}{}",
+            CALLBACK_PAYLOAD_VERSION, CALLBACK_PAYLOAD_SEPARATOR, fields
+        );
+        let data = format!(
+            "{}{}{}",
+            signed,
+            CALLBACK_PAYLOAD_SEPARATOR,
+            sign(secret, &signed)
+        );
+        if data.len() > MAX_CALLBACK_DATA_LENGTH {
+            dbg!("callback data is too long", &data);
+            return None;
+        }
+
+        Some(data)
+    }
+
+    /// Decodes the payload from callback data, checking that it was signed with the given secret.
+    pub(crate) fn decode(data: &str, secret: &[u8]) -> Result<CallbackPayload, PayloadError> {
+        if data.split(CALLBACK_PAYLOAD_SEPARATOR).next() != Some(CALLBACK_PAYLOAD_VERSION) {
+            return Err(PayloadError::Outdated);
+        }
+        let (signed, signature) = data
+            .rsplit_once(CALLBACK_PAYLOAD_SEPARATOR)
+            .ok_or(PayloadError::Unknown)?;
+        verify(secret, signed, signature)?;
+
+        let mut fields = signed.split(CALLBACK_PAYLOAD_SEPARATOR).skip(1);
+
+        let payload = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some("r"), Some(code), Some(spoiler_id), None) if !spoiler_id.is_empty() => {
+                RevealPolicy::from_code(code).map(|policy| CallbackPayload::Reveal {
+                    policy,
+                    spoiler_id: spoiler_id.to_string(),
+                })
+            }
+            (Some("y"), Some(spoiler_id), None, None) if !spoiler_id.is_empty() => {
+                Some(CallbackPayload::Confirm {
+                    spoiler_id: spoiler_id.to_string(),
+                })
+            }
+            (Some("n"), Some(spoiler_id), None, None) if !spoiler_id.is_empty() => {
+                Some(CallbackPayload::NextPage {
+                    spoiler_id: spoiler_id.to_string(),
+                })
+            }
+            (Some("b"), Some(spoiler_id), None, None) if !spoiler_id.is_empty() => {
+                Some(CallbackPayload::Blur {
+                    spoiler_id: spoiler_id.to_string(),
+                })
+            }
+            (Some("s"), Some(name), None, None) => {
+                Setting::from_name(name).map(CallbackPayload::ChangeSetting)
+            }
+            _ => None,
+        };
+        payload.ok_or(PayloadError::Unknown)
+    }
+}
+
+/// Returns the base64url encoded signature of the data.
+fn sign(secret: &[u8], data: &str) -> String {
+    let signature = mac(secret, data).finalize().into_bytes();
+
+    base64::encode_config(&signature[..SIGNATURE_BYTES], base64::URL_SAFE_NO_PAD)
+}
+
+/// Checks that the base64url encoded signature belongs to the data.
+fn verify(secret: &[u8], data: &str, signature: &str) -> Result<(), PayloadError> {
+    let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| PayloadError::Forged)?;
+    if signature.len() != SIGNATURE_BYTES {
+        return Err(PayloadError::Forged);
+    }
+
+    mac(secret, data)
+        .verify_truncated_left(&signature)
+        .map_err(|_| PayloadError::Forged)
+}
+
+/// Returns the HMAC over the data.
+fn mac(secret: &[u8], data: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(format!("{}:{}", SIGNATURE_CONTEXT, data).as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::state::reveal::MAX_TAPS;
+
+    static SECRET: &[u8] = b"a secret only the bot knows";
+
+    fn policy() -> impl Strategy<Value = RevealPolicy> {
+        prop_oneof![
+            (1..=MAX_TAPS).prop_map(RevealPolicy::Taps),
+            Just(RevealPolicy::Confirm),
+            Just(RevealPolicy::TypeWord),
+        ]
+    }
+
+    fn payload(
+        spoiler_id: impl Strategy<Value = String>,
+    ) -> impl Strategy<Value = CallbackPayload> {
+        let setting = prop_oneof![
+            Just(Setting::Moderation),
+            Just(Setting::Expiration),
+            Just(Setting::Mode),
+            Just(Setting::Author),
+        ];
+        spoiler_id.prop_flat_map(move |spoiler_id| {
+            prop_oneof![
+                policy().prop_map({
+                    let spoiler_id = spoiler_id.clone();
+                    move |policy| CallbackPayload::Reveal {
+                        policy,
+                        spoiler_id: spoiler_id.clone(),
+                    }
+                }),
+                Just(CallbackPayload::Confirm {
+                    spoiler_id: spoiler_id.clone(),
+                }),
+                Just(CallbackPayload::NextPage {
+                    spoiler_id: spoiler_id.clone(),
+                }),
+                Just(CallbackPayload::Blur { spoiler_id }),
+                setting.clone().prop_map(CallbackPayload::ChangeSetting),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn spoiler_ids_fit_into_callback_data(payload in payload("[a-zA-Z0-9]{12}")) {
+            let data = payload.encode(SECRET);
+
+            prop_assert!(data.is_some());
+            prop_assert_eq!(CallbackPayload::decode(&data.unwrap(), SECRET), Ok(payload));
+        }
+
+        #[test]
+        fn encoded_payloads_decode_to_themselves(payload in payload("[a-zA-Z0-9]{1,48}")) {
+            match payload.encode(SECRET) {
+                Some(data) => {
+                    prop_assert!(data.len() <= MAX_CALLBACK_DATA_LENGTH);
+                    prop_assert_eq!(CallbackPayload::decode(&data, SECRET), Ok(payload));
+                }
+                None => match &payload {
+                    CallbackPayload::Reveal { spoiler_id, .. }
+                    | CallbackPayload::Confirm { spoiler_id }
+                    | CallbackPayload::NextPage { spoiler_id }
+                    | CallbackPayload::Blur { spoiler_id } => {
+                        // the shortest prefix is "v2:r:2:", the signature takes ":" and 22 more
+                        prop_assert!(spoiler_id.len() > MAX_CALLBACK_DATA_LENGTH - 7 - 23);
+                    }
+                    CallbackPayload::ChangeSetting(_) => prop_assert!(false, "settings always fit"),
+                },
+            }
+        }
+
+        #[test]
+        fn tampered_data_is_rejected(
+            payload in payload("[a-zA-Z0-9]{12}"),
+            position in any::<prop::sample::Index>(),
+            replacement in "[a-zA-Z0-9:_-]",
+        ) {
+            let data = payload.encode(SECRET).unwrap();
+            let position = position.index(data.len());
+            let mut tampered = data.clone();
+            tampered.replace_range(position..=position, &replacement);
+            prop_assume!(tampered != data);
+
+            prop_assert!(CallbackPayload::decode(&tampered, SECRET).is_err());
+        }
+
+        #[test]
+        fn decoding_anything_does_not_panic(data in "\\PC{0,64}") {
+            let _ = CallbackPayload::decode(&data, SECRET);
+        }
+    }
+
+    #[test]
+    fn data_is_limited_to_64_bytes() {
+        let fits = |length| {
+            CallbackPayload::NextPage {
+                spoiler_id: "a".repeat(length),
+            }
+            .encode(SECRET)
+        };
+        // "v2:n:" and ":" around the id, followed by 22 characters of signature
+        let longest = MAX_CALLBACK_DATA_LENGTH - 6 - 22;
+
+        assert_eq!(fits(longest).map(|data| data.len()), Some(64));
+        assert_eq!(fits(longest + 1), None);
+    }
+
+    #[test]
+    fn data_signed_with_another_secret_is_forged() {
+        let data = CallbackPayload::Confirm {
+            spoiler_id: "abc123".to_string(),
+        }
+        .encode(b"another secret")
+        .unwrap();
+
+        assert_eq!(
+            CallbackPayload::decode(&data, SECRET),
+            Err(PayloadError::Forged)
+        );
+    }
+
+    #[test]
+    fn data_of_other_versions_is_outdated() {
+        assert_eq!(
+            CallbackPayload::decode("abc123", SECRET),
+            Err(PayloadError::Outdated)
+        );
+        assert_eq!(
+            CallbackPayload::decode("v1:r:2:abc123", SECRET),
+            Err(PayloadError::Outdated)
+        );
+    }
+
+    #[test]
+    fn unknown_payloads_are_rejected_even_when_signed() {
+        let signed = "v2:r:0:abc123";
+        let data = format!("{}:{}", signed, sign(SECRET, signed));
+
+        assert_eq!(
+            CallbackPayload::decode(&data, SECRET),
+            Err(PayloadError::Unknown)
+        );
+    }
+}
@@ -0,0 +1,372 @@
+//! The grammar of inline queries that create a spoiler.
+//!
+//! A query consists of an optional title, the content to spoil, an optional expiration time and
+//! optional options, in this order:
+//! ```text
+//! title ::: content /expiry [options]
+//! ```
+//! - The title is separated from the content by the first [`SPOILER_TITLE_SEPARATOR`], any later
+//!   one is part of the content.
+//! - The expiration time is a word of its own at the end, e.g. `/2d`, and is parsed the same way
+//!   as in [`util::parse_duration`].
+//! - The options are the [`Mode`]s to offer, separated by commas or spaces, in square brackets at
+//!   the very end, e.g. `[minor, native]`.
+//!
+//! A backslash escapes `:`, `/`, `[` and itself, so `a \::: b` is a spoiler without title and
+//! `\/2d` is not taken as an expiration time.
+use std::time::Duration;
+
+use crate::{state::reveal::RevealPolicy, strings::SPOILER_TITLE_SEPARATOR, util};
+
+/// The characters a backslash escapes.
+const ESCAPABLE: [char; 4] = ['\\', ':', '/', '['];
+
+/// A spoiler as described by an inline query.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub(crate) struct SpoilerQuery {
+    /// The title shown in place of the content, if one was given.
+    pub(crate) title: Option<String>,
+    /// The content to spoil.
+    pub(crate) content: String,
+    /// The amount of time until the spoiler expires, if one was given.
+    pub(crate) expires_in: Option<Duration>,
+    /// The kinds of spoilers to offer. All of them are offered if none were given.
+    pub(crate) modes: Vec<Mode>,
+}
+
+/// A kind of spoiler that may be offered for a query.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(crate) enum Mode {
+    /// A spoiler revealed with a button according to the policy.
+    Reveal(RevealPolicy),
+    /// A spoiler using Telegram's own spoiler formatting.
+    Native,
+}
+
+impl Mode {
+    /// Returns the mode with the given name, ignoring case.
+    fn from_name(name: &str) -> Option<Mode> {
+        match name.to_lowercase().as_str() {
+            "minor" => Some(Mode::Reveal(RevealPolicy::Taps(1))),
+            "major" => Some(Mode::Reveal(RevealPolicy::Taps(2))),
+            "confirm" => Some(Mode::Reveal(RevealPolicy::Confirm)),
+            "hidden" => Some(Mode::Reveal(RevealPolicy::TypeWord)),
+            "native" => Some(Mode::Native),
+            _ => None,
+        }
+    }
+}
+
+/// Why an inline query does not describe a spoiler.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub(crate) enum QueryError {
+    /// There is nothing to spoil, e.g. only a title was given.
+    EmptyContent,
+    /// The word following the `/` at the end is not an expiration time, e.g. `/2x`.
+    InvalidExpiry(String),
+    /// An option is not the name of a [`Mode`].
+    UnknownOption(String),
+}
+
+/// A character of the query along with whether it was escaped.
+type Lexeme = (char, bool);
+
+impl SpoilerQuery {
+    /// Parses the query according to the [grammar](self).
+    pub(crate) fn parse(query: &str) -> Result<SpoilerQuery, QueryError> {
+        let lexemes = lex(query);
+        let mut end = trim_end(&lexemes, lexemes.len());
+
+        let mut modes = Vec::new();
+        if let Some(start) = options_start(&lexemes[..end]) {
+            let options = unescape(&lexemes[start + 1..end - 1]);
+            for option in options
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|option| !option.is_empty())
+            {
+                match Mode::from_name(option) {
+                    Some(mode) if !modes.contains(&mode) => modes.push(mode),
+                    Some(_) => {}
+                    None => return Err(QueryError::UnknownOption(option.to_string())),
+                }
+            }
+            end = trim_end(&lexemes, start);
+        }
+
+        let mut expires_in = None;
+        if let Some(start) = expiry_start(&lexemes[..end]) {
+            let expiry = unescape(&lexemes[start..end]);
+            match util::parse_duration(&expiry) {
+                Some(duration) => expires_in = Some(duration),
+                None => return Err(QueryError::InvalidExpiry(expiry[1..].to_string())),
+            }
+            end = trim_end(&lexemes, start);
+        }
+
+        let (title, content) = match separator_start(&lexemes[..end]) {
+            Some(start) => {
+                let title = unescape(&lexemes[..start]).trim().to_string();
+                let content = &lexemes[start + SPOILER_TITLE_SEPARATOR.chars().count()..end];
+                (Some(title).filter(|title| !title.is_empty()), content)
+            }
+            None => (None, &lexemes[..end]),
+        };
+        let content = unescape(content).trim().to_string();
+        if content.is_empty() {
+            return Err(QueryError::EmptyContent);
+        }
+
+        Ok(SpoilerQuery {
+            title,
+            content,
+            expires_in,
+            modes,
+        })
+    }
+
+    /// Returns `true` if the query asks for the given mode, which it does if it did not ask for
+    /// any mode in particular.
+    pub(crate) fn offers(&self, mode: Mode) -> bool {
+        self.modes.is_empty() || self.modes.contains(&mode)
+    }
+}
+
+/// Splits the query into its characters, resolving escapes.
+fn lex(query: &str) -> Vec<Lexeme> {
+    let mut lexemes = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(&next) if c == '\\' && ESCAPABLE.contains(&next) => {
+                chars.next();
+                lexemes.push((next, true));
+            }
+            _ => lexemes.push((c, false)),
+        }
+    }
+    lexemes
+}
+
+/// Joins the characters back together.
+fn unescape(lexemes: &[Lexeme]) -> String {
+    lexemes.iter().map(|(c, _)| c).collect()
+}
+
+/// Returns the end of the characters up to `end` without trailing whitespace.
+fn trim_end(lexemes: &[Lexeme], end: usize) -> usize {
+    lexemes[..end]
+        .iter()
+        .rposition(|(c, _)| !c.is_whitespace())
+        .map_or(0, |last| last + 1)
+}
+
+/// Returns `true` if the character at the given index starts a word.
+fn starts_word(lexemes: &[Lexeme], index: usize) -> bool {
+    index == 0 || lexemes[index - 1].0.is_whitespace()
+}
+
+/// Returns the index of the `[` opening the options, if the characters end with options.
+fn options_start(lexemes: &[Lexeme]) -> Option<usize> {
+    if lexemes.last() != Some(&(']', false)) {
+        return None;
+    }
+    let start = lexemes.iter().rposition(|lexeme| *lexeme == ('[', false))?;
+
+    Some(start).filter(|start| starts_word(lexemes, *start))
+}
+
+/// Returns the index of the `/` starting the expiration time, if the last word of the characters
+/// is one.
+///
+/// Only words starting with a `/` followed by a digit count, so a trailing path or URL is not
+/// mistaken for an invalid expiration time.
+fn expiry_start(lexemes: &[Lexeme]) -> Option<usize> {
+    let start = lexemes
+        .iter()
+        .rposition(|(c, _)| c.is_whitespace())
+        .map_or(0, |i| i + 1);
+    let word = &lexemes[start..];
+
+    match word {
+        [('/', false), (digit, _), ..] if digit.is_ascii_digit() => Some(start),
+        _ => None,
+    }
+}
+
+/// Returns the index of the first unescaped [`SPOILER_TITLE_SEPARATOR`].
+fn separator_start(lexemes: &[Lexeme]) -> Option<usize> {
+    let separator: Vec<Lexeme> = SPOILER_TITLE_SEPARATOR
+        .chars()
+        .map(|c| (c, false))
+        .collect();
+
+    lexemes
+        .windows(separator.len())
+        .position(|window| window == separator.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::util::{DAY_IN_SECS, HOUR_IN_SECS};
+
+    /// Escapes all characters of the text that have a meaning in queries.
+    fn escape(text: &str) -> String {
+        text.chars()
+            .flat_map(|c| {
+                if ESCAPABLE.contains(&c) {
+                    vec!['\\', c]
+                } else {
+                    vec![c]
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_all_parts() {
+        assert_eq!(
+            SpoilerQuery::parse("Harry Potter ::: Snape kills Dumbledore /2d [minor, native]"),
+            Ok(SpoilerQuery {
+                title: Some("Harry Potter".to_string()),
+                content: "Snape kills Dumbledore".to_string(),
+                expires_in: Some(Duration::from_secs(2 * DAY_IN_SECS)),
+                modes: vec![Mode::Reveal(RevealPolicy::Taps(1)), Mode::Native],
+            })
+        );
+    }
+
+    #[test]
+    fn parts_may_be_omitted() {
+        assert_eq!(
+            SpoilerQuery::parse("Snape kills Dumbledore"),
+            Ok(SpoilerQuery {
+                title: None,
+                content: "Snape kills Dumbledore".to_string(),
+                expires_in: None,
+                modes: Vec::new(),
+            })
+        );
+        assert_eq!(
+            SpoilerQuery::parse(" ::: Snape kills Dumbledore /3h").map(|q| (q.title, q.expires_in)),
+            Ok((None, Some(Duration::from_secs(3 * HOUR_IN_SECS))))
+        );
+        assert_eq!(
+            SpoilerQuery::parse("Snape kills Dumbledore [HIDDEN confirm hidden]").map(|q| q.modes),
+            Ok(vec![
+                Mode::Reveal(RevealPolicy::TypeWord),
+                Mode::Reveal(RevealPolicy::Confirm),
+            ])
+        );
+    }
+
+    #[test]
+    fn only_the_first_separator_ends_the_title() {
+        assert_eq!(
+            SpoilerQuery::parse("a ::: b ::: c").map(|q| (q.title, q.content)),
+            Ok((Some("a".to_string()), "b ::: c".to_string()))
+        );
+    }
+
+    #[test]
+    fn escaped_characters_lose_their_meaning() {
+        assert_eq!(
+            SpoilerQuery::parse(r"a \::: b \/2d \[minor]"),
+            Ok(SpoilerQuery {
+                title: None,
+                content: "a ::: b /2d [minor]".to_string(),
+                expires_in: None,
+                modes: Vec::new(),
+            })
+        );
+        assert_eq!(
+            SpoilerQuery::parse(r"C:\\ \n").map(|q| q.content),
+            Ok(r"C:\ \n".to_string())
+        );
+    }
+
+    #[test]
+    fn trailing_paths_are_content() {
+        assert_eq!(
+            SpoilerQuery::parse("see /usr/bin").map(|q| (q.content, q.expires_in)),
+            Ok(("see /usr/bin".to_string(), None))
+        );
+        assert_eq!(
+            SpoilerQuery::parse("spoiler a[1]").map(|q| (q.content, q.modes)),
+            Ok(("spoiler a[1]".to_string(), Vec::new()))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        assert_eq!(
+            SpoilerQuery::parse("title ::: /2d [minor]"),
+            Err(QueryError::EmptyContent)
+        );
+        assert_eq!(SpoilerQuery::parse("   "), Err(QueryError::EmptyContent));
+        assert_eq!(
+            SpoilerQuery::parse("spoiler /2x"),
+            Err(QueryError::InvalidExpiry("2x".to_string()))
+        );
+        assert_eq!(
+            SpoilerQuery::parse("spoiler /99999999999999999999y"),
+            Err(QueryError::InvalidExpiry(
+                "99999999999999999999y".to_string()
+            ))
+        );
+        assert_eq!(
+            SpoilerQuery::parse("spoiler [minor, loud]"),
+            Err(QueryError::UnknownOption("loud".to_string()))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn parsing_anything_does_not_panic(query in "\\PC{0,64}") {
+            let _ = SpoilerQuery::parse(&query);
+        }
+
+        #[test]
+        fn escaped_content_is_kept_as_is(content in "\\PC*[^\\s]") {
+            let content = content.trim().to_string();
+
+            prop_assert_eq!(
+                SpoilerQuery::parse(&escape(&content)),
+                Ok(SpoilerQuery {
+                    title: None,
+                    content,
+                    expires_in: None,
+                    modes: Vec::new(),
+                })
+            );
+        }
+
+        #[test]
+        fn escaped_parts_are_found_again(
+            title in "[^\\s]\\PC*[^\\s]",
+            content in "[^\\s]\\PC*[^\\s]",
+            days in 1..1000u64,
+            minor in any::<bool>(),
+        ) {
+            let options = if minor { " [minor]" } else { "" };
+            let query = format!("{} ::: {} /{}d{}", escape(&title), escape(&content), days, options);
+
+            prop_assert_eq!(
+                SpoilerQuery::parse(&query),
+                Ok(SpoilerQuery {
+                    title: Some(title),
+                    content,
+                    expires_in: Some(Duration::from_secs(days * DAY_IN_SECS)),
+                    modes: if minor {
+                        vec![Mode::Reveal(RevealPolicy::Taps(1))]
+                    } else {
+                        Vec::new()
+                    },
+                })
+            );
+        }
+    }
+}
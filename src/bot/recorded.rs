@@ -0,0 +1,210 @@
+//! Updates as recorded from the Bot API, for tests of the handlers.
+use std::env;
+
+use serde_json::{json, Value};
+use teloxide::types::{
+    CallbackQuery, ChosenInlineResult, InlineQuery, Me, Message, Update, UpdateKind,
+};
+
+use crate::{
+    config::Config,
+    state::{expiry, expiry::Expirations, State},
+    util,
+};
+
+/// The id of the user sending the recorded updates.
+pub(crate) static USER_ID: u64 = 10000;
+
+/// Returns the update of the given kind, e.g. `message`, as received from the Bot API.
+fn update(kind: &str, content: Value) -> UpdateKind {
+    // updates only deserialize from text, as they are received
+    let update = json!({
+        "update_id": 10000,
+        kind: content,
+    });
+    let update: Update = serde_json::from_str(&update.to_string()).unwrap();
+    update.kind
+}
+
+/// Returns the user sending the recorded updates.
+fn user() -> Value {
+    json!({
+        "id": USER_ID,
+        "is_bot": false,
+        "first_name": "Lara",
+        "username": "lara",
+        "language_code": "en",
+    })
+}
+
+/// Returns the message of an update with the given message, as received from the Bot API.
+pub(crate) fn message(message: Value) -> Message {
+    match update("message", message) {
+        UpdateKind::Message(message) => message,
+        kind => panic!("not a message: {:?}", kind),
+    }
+}
+
+/// Returns a text message sent to the bot in a private chat.
+///
+/// If the text starts with a command, such as `/start`, it is marked as one.
+pub(crate) fn private_message(text: &str) -> Message {
+    let mut message = json!({
+        "message_id": 42,
+        "from": user(),
+        "chat": {
+            "id": USER_ID,
+            "first_name": "Lara",
+            "username": "lara",
+            "type": "private",
+        },
+        "date": 1700000000,
+        "text": text,
+    });
+    if text.starts_with('/') {
+        let length = text
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .encode_utf16()
+            .count();
+        message["entities"] = json!([{ "offset": 0, "length": length, "type": "bot_command" }]);
+    }
+
+    self::message(message)
+}
+
+/// Returns a message sent to a group, with the given fields describing its content.
+pub(crate) fn group_message(content: Value) -> Message {
+    let mut message = json!({
+        "message_id": 1365,
+        "from": user(),
+        "chat": {
+            "id": -1001234567890i64,
+            "title": "Spoiler Club",
+            "type": "supergroup",
+        },
+        "date": 1700000000,
+    });
+    message
+        .as_object_mut()
+        .unwrap()
+        .extend(content.as_object().unwrap().clone());
+
+    self::message(message)
+}
+
+/// Returns a message sent to a group in reply to a text message of another user.
+pub(crate) fn group_reply(content: Value, replied_text: &str) -> Message {
+    let mut content = content;
+    content["reply_to_message"] = json!({
+        "message_id": 1364,
+        "from": { "id": 20000, "is_bot": false, "first_name": "Ron" },
+        "chat": { "id": -1001234567890i64, "title": "Spoiler Club", "type": "supergroup" },
+        "date": 1699999990,
+        "text": replied_text,
+    });
+
+    group_message(content)
+}
+
+/// Returns the given user as an administrator of a group, who may delete messages or not, as
+/// returned by `getChatMember` and `getChatAdministrators`.
+pub(crate) fn administrator(user: Value, can_delete_messages: bool) -> Value {
+    json!({
+        "status": "administrator",
+        "user": user,
+        "can_be_edited": false,
+        "is_anonymous": false,
+        "can_manage_chat": true,
+        "can_change_info": false,
+        "can_delete_messages": can_delete_messages,
+        "can_manage_video_chats": false,
+        "can_invite_users": true,
+        "can_restrict_members": false,
+        "can_promote_members": false,
+    })
+}
+
+/// Returns the bot as a user, as it shows up among the members of a group.
+pub(crate) fn bot_user() -> Value {
+    json!({ "id": 1, "is_bot": true, "first_name": "Spoiler", "username": "spoilerowobot" })
+}
+
+/// Returns the user sending the recorded updates as the creator of a group.
+pub(crate) fn creator() -> Value {
+    json!({ "status": "creator", "user": user(), "is_anonymous": false })
+}
+
+/// Returns the bot's identity as returned by `getMe`.
+pub(crate) fn me() -> Me {
+    serde_json::from_value(json!({
+        "id": 1,
+        "is_bot": true,
+        "first_name": "Spoiler",
+        "username": "spoilerowobot",
+        "can_join_groups": true,
+        "can_read_all_group_messages": false,
+        "supports_inline_queries": true,
+        "has_main_web_app": false,
+    }))
+    .unwrap()
+}
+
+/// Returns a tap on a button with the given callback data below a message posted via inline mode.
+pub(crate) fn callback_query(data: &str) -> CallbackQuery {
+    let query = json!({
+        "id": "4382bfdwdsb323b2d9",
+        "from": user(),
+        "inline_message_id": "BAAAAHmeAABgB1pVnQ2hHuE",
+        "chat_instance": "-5305385736583738117",
+        "data": data,
+    });
+    match update("callback_query", query) {
+        UpdateKind::CallbackQuery(query) => query,
+        kind => panic!("not a callback query: {:?}", kind),
+    }
+}
+
+/// Returns an inline query with the given text.
+pub(crate) fn inline_query(text: &str) -> InlineQuery {
+    let query = json!({
+        "id": "1437238543719584301",
+        "from": user(),
+        "query": text,
+        "offset": "",
+        "chat_type": "sender",
+    });
+    match update("inline_query", query) {
+        UpdateKind::InlineQuery(query) => query,
+        kind => panic!("not an inline query: {:?}", kind),
+    }
+}
+
+/// Returns the feedback that the inline result with the given id was posted.
+pub(crate) fn chosen_inline_result(result_id: &str) -> ChosenInlineResult {
+    let result = json!({
+        "result_id": result_id,
+        "from": user(),
+        "inline_message_id": "BAAAAHmeAABgB1pVnQ2hHuE",
+        "query": "Harry Potter ::: Snape kills Dumbledore",
+    });
+    match update("chosen_inline_result", result) {
+        UpdateKind::ChosenInlineResult(result) => result,
+        kind => panic!("not a chosen inline result: {:?}", kind),
+    }
+}
+
+/// Creates a state with the given configuration for the bot returned by [`me`].
+///
+/// The expirations are returned as well, so the state can keep scheduling spoilers.
+pub(crate) fn state(config: Config) -> (State, Expirations) {
+    let config = Config {
+        settings_file: env::temp_dir()
+            .join(format!("spoilerowobot-test-{}.json", util::random_id())),
+        ..config
+    };
+    let (scheduler, expirations) = expiry::channel();
+
+    (State::new(config, &me(), scheduler), expirations)
+}
@@ -1,285 +1,299 @@
 //! Methods related to spoiler creation
 use std::sync::Arc;
 
-use tbot::{
-    contexts::fields::Message,
-    contexts::methods::ChatMethods,
-    contexts::{
-        Animation, Audio, Contact, Dice, Document, Location, Photo, Sticker, Text, Video,
-        VideoNote, Voice,
-    },
-    types::keyboard::inline::{Button, ButtonKind, Markup},
+use teloxide::{
+    prelude::*,
+    sugar::request::RequestReplyExt,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile, ParseMode},
+    utils::html,
 };
 use tokio::time::Duration;
 
 use crate::{
-    state::{spoiler::Content, State},
+    bot::{command::start, payload::CallbackPayload},
+    state::{
+        rate_limit::{Action, Subject},
+        spoiler::{Content, Spoiler},
+        token::TokenPurpose,
+        State,
+    },
     strings::{
-        bot_replies::{NOW_SEND_A_TITLE, SPOILER_READY},
-        INLINE_QUERY_SEPARATOR, SEND_IT,
+        bot_replies::{
+            DRAFT_EVICTED, DRAFT_NOT_FOUND, NOW_SEND_A_TITLE, SLOW_DOWN, SPOILER_NOT_FOUND,
+            SPOILER_READY, WRONG_CONFIRMATION_WORD,
+        },
+        CONFIRMATION_WORD, INLINE_QUERY_SEPARATOR, SEND_BLURRED, SEND_IT,
     },
     util,
 };
 
 /// Handle text messages.
-pub(crate) async fn text(context: Arc<Text>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
-
-    if state.waiting_for_spoiler(&user_id) {
+///
+/// If the user was asked to type the confirmation word for a spoiler, the message is taken as the
+/// answer and the spoiler is revealed if it matches [`CONFIRMATION_WORD`].
+pub(crate) async fn text(bot: Bot, message: Message, state: Arc<State>) -> ResponseResult<()> {
+    let (user_id, text) = match (&message.from, message.text()) {
+        (Some(user), Some(text)) => (user.id, text),
+        _ => return Ok(()),
+    };
+
+    if let Some(spoiler_id) = state.take_awaited_confirmation(&user_id) {
+        confirm_word(&bot, &message, user_id, text, &state, spoiler_id).await;
+    } else if state.waiting_for_spoiler(&user_id) {
         // check if we are in the spoiler creation process
-        new_spoiler(context.clone(), state.clone()).await;
+        new_spoiler(&bot, &message, user_id, text, &state).await;
     } else if state.waiting_for_title(&user_id) {
-        set_spoiler_title(context.clone(), state.clone()).await;
+        set_spoiler_title(&bot, &message, user_id, text, &state).await;
     }
+    Ok(())
 }
 
-/// Create a new state and notify the user what to do next
-async fn new_spoiler(context: Arc<Text>, state: Arc<State>) {
-    let user_id = context.from().as_ref().unwrap().id;
-
-    state.new_spoiler(user_id.to_owned(), Content::Text(context.text.to_owned()));
-    let _ = state.set_waiting_for_title(user_id);
-
-    if let Err(e) = context.send_message_in_reply(NOW_SEND_A_TITLE).call().await {
-        dbg!(e);
-    }
-}
-
-/// Set the spoiler title and return the created spoiler to the user
-async fn set_spoiler_title(context: Arc<Text>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
-
-    let expires_in: Option<Duration> = util::parse_duration(&context.text.value);
-
-    let mut spoiler_id = String::from(INLINE_QUERY_SEPARATOR);
-    spoiler_id.push_str(&*state.set_spoiler_title_and_expiration(
-        user_id,
-        context.text.value.to_owned(),
-        expires_in,
-    ));
-    let reply_markup: Markup = &[&[Button::new(
-        SEND_IT,
-        ButtonKind::SwitchInlineQuery(&spoiler_id),
-    )]];
-
-    if let Err(e) = context
-        .send_message_in_reply(SPOILER_READY)
-        .reply_markup(reply_markup)
-        .call()
-        .await
-    {
-        dbg!(e);
-    }
-}
-
-/// Handle Animation messages.
-pub(crate) async fn animation(context: Arc<Animation>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
-
-    if !state.waiting_for_spoiler(&user_id) {
+/// Reveals the spoiler if the user typed the confirmation word.
+async fn confirm_word(
+    bot: &Bot,
+    message: &Message,
+    user_id: UserId,
+    text: &str,
+    state: &State,
+    spoiler_id: String,
+) {
+    let reply = if !text.trim().eq_ignore_ascii_case(CONFIRMATION_WORD) {
+        WRONG_CONFIRMATION_WORD
+    } else if let Some(spoiler) = state.get_spoiler(&spoiler_id) {
+        start::send_spoiler(bot, user_id, spoiler).await;
         return;
-    }
-
-    let _ = state.set_waiting_for_title(user_id);
-    state.new_spoiler(
-        user_id.to_owned(),
-        Content::Animation(
-            Box::from(context.animation.to_owned()),
-            context.caption.clone(),
-        ),
-    );
-
-    if let Err(e) = context.send_message_in_reply(NOW_SEND_A_TITLE).call().await {
-        dbg!(e.to_string());
-    }
-}
-
-/// Handle Audio Messages
-pub(crate) async fn audio(context: Arc<Audio>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
-
-    if !state.waiting_for_spoiler(&user_id) {
-        return;
-    }
+    } else {
+        SPOILER_NOT_FOUND
+    };
 
-    let _ = state.set_waiting_for_title(user_id);
-    state.new_spoiler(
-        user_id.to_owned(),
-        Content::Audio(Box::from(context.audio.to_owned()), context.caption.clone()),
-    );
-
-    if let Err(e) = context.send_message_in_reply(NOW_SEND_A_TITLE).call().await {
-        dbg!(e.to_string());
-    }
+    util::reply(bot, message, reply).await;
 }
 
-/// Handle Contact Messages
-pub(crate) async fn contact(context: Arc<Contact>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
-
-    if !state.waiting_for_spoiler(&user_id) {
-        return;
-    }
-
+/// Create a new state and notify the user what to do next
+async fn new_spoiler(bot: &Bot, message: &Message, user_id: UserId, text: &str, state: &State) {
+    let evicted = state.new_spoiler(user_id, Content::Text(text.to_owned()));
     let _ = state.set_waiting_for_title(user_id);
-    state.new_spoiler(
-        user_id.to_owned(),
-        Content::Contact(context.contact.to_owned()),
-    );
 
-    if let Err(e) = context.send_message_in_reply(NOW_SEND_A_TITLE).call().await {
-        dbg!(e.to_string());
-    }
+    util::reply(bot, message, NOW_SEND_A_TITLE).await;
+    notify_evicted(bot, evicted).await;
 }
 
-/// Handle Dice Messages
-pub(crate) async fn dice(context: Arc<Dice>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
-
-    if !state.waiting_for_spoiler(&user_id) {
-        return;
-    }
-
-    let _ = state.set_waiting_for_title(user_id.to_owned());
-    state.new_spoiler(user_id, Content::Dice(context.dice.to_owned()));
-
-    if let Err(e) = context.send_message_in_reply(NOW_SEND_A_TITLE).call().await {
-        dbg!(e.to_string());
+/// Tells the user whose draft was discarded to make room for a new one about it.
+async fn notify_evicted(bot: &Bot, evicted: Option<UserId>) {
+    if let Some(user) = evicted {
+        if let Err(e) = bot.send_message(user, DRAFT_EVICTED).await {
+            dbg!(e);
+        }
     }
 }
 
-/// Handle Document Messages
-pub(crate) async fn document(context: Arc<Document>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
-
-    if !state.waiting_for_spoiler(&user_id) {
+/// Set the spoiler title and return the created spoiler to the user
+///
+/// Photos, videos and GIFs may also be [sent blurred](send_blurred), so they can be forwarded
+/// anywhere.
+async fn set_spoiler_title(
+    bot: &Bot,
+    message: &Message,
+    user_id: UserId,
+    text: &str,
+    state: &State,
+) {
+    // the draft stays around, so the user may send the title again in a moment
+    if !state.allow(Action::Create, &[Subject::User(user_id)]) {
+        util::reply(bot, message, SLOW_DOWN).await;
         return;
     }
 
-    let _ = state.set_waiting_for_title(user_id.to_owned());
-    state.new_spoiler(
-        user_id,
-        Content::Document(
-            Box::from(context.document.to_owned()),
-            context.caption.clone(),
-        ),
+    let expires_in: Option<Duration> = util::parse_duration(&text.to_owned());
+
+    let spoiler_id =
+        match state.set_spoiler_title_and_expiration(user_id, text.to_owned(), expires_in) {
+            Some(spoiler_id) => spoiler_id,
+            None => {
+                util::reply(bot, message, DRAFT_NOT_FOUND).await;
+                return;
+            }
+        };
+    // only the creator may share the spoiler via inline query
+    let query = format!(
+        "{}{}",
+        INLINE_QUERY_SEPARATOR,
+        state.issue_token(TokenPurpose::Share, &spoiler_id, user_id)
     );
-
-    if let Err(e) = context.send_message_in_reply(NOW_SEND_A_TITLE).call().await {
-        dbg!(e.to_string());
-    }
-}
-
-/// Handle Location Messages
-pub(crate) async fn location(context: Arc<Location>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
-
-    if !state.waiting_for_spoiler(&user_id) {
-        return;
+    let mut buttons = vec![vec![InlineKeyboardButton::switch_inline_query(
+        SEND_IT, query,
+    )]];
+    let blurrable = state
+        .get_spoiler(&spoiler_id)
+        .is_some_and(|spoiler| spoiler.content.can_be_blurred());
+    let blur = CallbackPayload::Blur {
+        spoiler_id: spoiler_id.clone(),
+    };
+    if let Some(data) = blur.encode(&state.config.secret).filter(|_| blurrable) {
+        buttons.push(vec![InlineKeyboardButton::callback(SEND_BLURRED, data)]);
     }
-    let _ = state.set_waiting_for_title(user_id.to_owned());
-    state.new_spoiler(user_id, Content::Location(context.location.to_owned()));
+    let reply_markup = InlineKeyboardMarkup::new(buttons);
 
-    if let Err(e) = context.send_message_in_reply(NOW_SEND_A_TITLE).call().await {
-        dbg!(e.to_string());
+    if let Err(e) = bot
+        .send_message(message.chat.id, SPOILER_READY)
+        .reply_to(message.id)
+        .reply_markup(reply_markup)
+        .await
+    {
+        dbg!(e);
     }
 }
 
-/// Handle Photo Messages
-pub(crate) async fn photo(context: Arc<Photo>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
-
-    if !state.waiting_for_spoiler(&user_id) {
-        return;
-    }
-
-    let _ = state.set_waiting_for_title(user_id.to_owned());
-    state.new_spoiler(
-        user_id,
-        Content::Photo(
-            context.photo.to_owned(),
-            context.caption.clone(),
-            context.media_group_id.clone(),
-        ),
-    );
-
-    if let Err(e) = context.send_message_in_reply(NOW_SEND_A_TITLE).call().await {
-        dbg!(e.to_string());
+/// Sends the user a copy of a photo, video or GIF spoiler that is blurred by Telegram itself, so
+/// it can be forwarded even to chats without the bot.
+///
+/// The caption is hidden behind Telegram's spoiler formatting below the title. Other kinds of
+/// content are not sent at all.
+pub(crate) async fn send_blurred(bot: &Bot, user_id: UserId, spoiler: &Spoiler) {
+    let title = spoiler.title.as_deref().unwrap_or_default();
+    let sent = match &spoiler.content {
+        Content::Animation(animation, caption) => bot
+            .send_animation(user_id, InputFile::file_id(animation.file.id.clone()))
+            .caption(blurred_caption(title, caption))
+            .parse_mode(ParseMode::Html)
+            .has_spoiler(true)
+            .await
+            .map(drop),
+        Content::Photo(photos, caption, _media_group_id) => {
+            let photo = match photos.iter().max_by_key(|photo| photo.width) {
+                Some(photo) => photo,
+                None => return,
+            };
+
+            bot.send_photo(user_id, InputFile::file_id(photo.file.id.clone()))
+                .caption(blurred_caption(title, caption))
+                .parse_mode(ParseMode::Html)
+                .has_spoiler(true)
+                .await
+                .map(drop)
+        }
+        Content::Video(video, caption, _media_group_id) => bot
+            .send_video(user_id, InputFile::file_id(video.file.id.clone()))
+            .caption(blurred_caption(title, caption))
+            .parse_mode(ParseMode::Html)
+            .has_spoiler(true)
+            .await
+            .map(drop),
+        _ => return,
+    };
+    if let Err(e) = sent {
+        dbg!(e);
     }
 }
 
-/// Handle Sticker Messages
-pub(crate) async fn sticker(context: Arc<Sticker>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
-
-    if !state.waiting_for_spoiler(&user_id) {
-        return;
+/// Returns the caption of a blurred copy: the title in bold, followed by the original caption
+/// hidden behind Telegram's spoiler formatting.
+fn blurred_caption(title: &str, caption: &str) -> String {
+    let heading = if title.is_empty() {
+        String::new()
+    } else {
+        format!("<b>{}</b>\n", html::escape(title))
+    };
+    if caption.is_empty() {
+        return heading.trim_end().to_string();
     }
 
-    let _ = state.set_waiting_for_title(user_id.to_owned());
-    state.new_spoiler(
-        user_id,
-        Content::Sticker(Box::from(context.sticker.to_owned())),
-    );
-
-    if let Err(e) = context.send_message_in_reply(NOW_SEND_A_TITLE).call().await {
-        dbg!(e.to_string());
-    }
+    format!(
+        "{}<tg-spoiler>{}</tg-spoiler>",
+        heading,
+        html::escape(caption)
+    )
 }
 
-/// Handle Video Messages
-pub(crate) async fn video(context: Arc<Video>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
+/// Handle media messages, i.e. every kind of message other than text that can be spoiled.
+pub(crate) async fn media(bot: Bot, message: Message, state: Arc<State>) -> ResponseResult<()> {
+    let user_id = match &message.from {
+        Some(user) => user.id,
+        None => return Ok(()),
+    };
 
     if !state.waiting_for_spoiler(&user_id) {
-        return;
+        return Ok(());
     }
 
+    let content = match Content::from_message(&message) {
+        Some(content) => content,
+        None => return Ok(()),
+    };
+    let evicted = state.new_spoiler(user_id, content);
     let _ = state.set_waiting_for_title(user_id);
-    state.new_spoiler(
-        user_id,
-        Content::Video(
-            Box::from(context.video.to_owned()),
-            context.caption.clone(),
-            context.media_group_id.clone(),
-        ),
-    );
 
-    if let Err(e) = context.send_message_in_reply(NOW_SEND_A_TITLE).call().await {
-        dbg!(e.to_string());
-    }
+    util::reply(&bot, &message, NOW_SEND_A_TITLE).await;
+    notify_evicted(&bot, evicted).await;
+    Ok(())
 }
 
-/// Handle Video Note Messages
-pub(crate) async fn video_note(context: Arc<VideoNote>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
-
-    if !state.waiting_for_spoiler(&user_id) {
-        return;
-    }
-
-    let _ = state.set_waiting_for_title(user_id.to_owned());
-    state.new_spoiler(user_id, Content::VideoNote(context.video_note.to_owned()));
-
-    if let Err(e) = context.send_message_in_reply(NOW_SEND_A_TITLE).call().await {
-        dbg!(e.to_string());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bot::{mock_api::MockApi, recorded},
+        config::Config,
+    };
+
+    #[test]
+    fn blurred_captions_hide_the_caption_below_the_title() {
+        assert_eq!(
+            blurred_caption("Finale <3", "Snape & Dumbledore"),
+            "<b>Finale &lt;3</b>\n<tg-spoiler>Snape &amp; Dumbledore</tg-spoiler>"
+        );
+        assert_eq!(blurred_caption("Finale", ""), "<b>Finale</b>");
+        assert_eq!(blurred_caption("", ""), "");
     }
-}
 
-/// Handle Voice Messages
-pub(crate) async fn voice(context: Arc<Voice>, state: Arc<State>) {
-    let user_id = context.from.as_ref().unwrap().id;
-    if !state.waiting_for_spoiler(&user_id) {
-        return;
+    #[tokio::test]
+    async fn text_spoilers_are_ready_to_be_sent_after_the_title() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        state.set_waiting_for_spoiler(UserId(recorded::USER_ID));
+
+        for text in ["Snape kills Dumbledore", "Harry Potter"] {
+            let message = recorded::private_message(text);
+            self::text(api.bot(), message, state.clone()).await.unwrap();
+        }
+
+        let replies = api.calls("sendMessage");
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0]["text"], NOW_SEND_A_TITLE);
+        assert_eq!(replies[1]["text"], SPOILER_READY);
+        assert_eq!(replies[1]["reply_parameters"]["message_id"], 42);
+        // texts cannot be blurred, so there is only the button to send it
+        let keyboard = replies[1]["reply_markup"]["inline_keyboard"]
+            .as_array()
+            .unwrap();
+        assert_eq!(keyboard.len(), 1);
+        assert_eq!(keyboard[0][0]["text"], SEND_IT);
+        let query = keyboard[0][0]["switch_inline_query"].as_str().unwrap();
+        assert!(query.starts_with(INLINE_QUERY_SEPARATOR), "{}", query);
     }
 
-    let _ = state.set_waiting_for_title(user_id.to_owned());
-    state.new_spoiler(
-        user_id,
-        Content::Voice(context.voice.to_owned(), context.caption.clone()),
-    );
-
-    if let Err(e) = context.send_message_in_reply(NOW_SEND_A_TITLE).call().await {
-        dbg!(e.to_string());
+    #[tokio::test]
+    async fn hidden_spoilers_need_the_confirmation_word() {
+        let api = MockApi::start().await;
+        let (state, _expirations) = recorded::state(Config::default());
+        let state = Arc::new(state);
+        let spoiler_id = state.create_spoiler(
+            None,
+            Content::Text("Snape kills Dumbledore".to_string()),
+            None,
+            None,
+        );
+
+        for word in ["please", CONFIRMATION_WORD] {
+            state.await_confirmation_word(UserId(recorded::USER_ID), spoiler_id.clone());
+            let message = recorded::private_message(word);
+            text(api.bot(), message, state.clone()).await.unwrap();
+        }
+
+        let replies = api.calls("sendMessage");
+        assert_eq!(replies[0]["text"], WRONG_CONFIRMATION_WORD);
+        assert_eq!(replies[1]["text"], "Snape kills Dumbledore");
+        assert_eq!(replies[1]["chat_id"], recorded::USER_ID);
     }
 }
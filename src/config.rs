@@ -0,0 +1,170 @@
+//! The bot's configuration.
+//!
+//! All options are read from environment variables once at startup.
+use std::{env, path::PathBuf, str::FromStr, time::Duration};
+
+use rand::Rng;
+use teloxide::types::{ChatId, UserId};
+use url::Url;
+
+use crate::{
+    state::rate_limit::RateLimits,
+    strings::{
+        CHAT_LIMIT_VARIABLE, CREATE_LIMIT_VARIABLE, DOUBLE_TAP_WINDOW_VARIABLE,
+        DRAFT_TIMEOUT_VARIABLE, FORWARD_CHAT_VARIABLE, MAX_DRAFTS_VARIABLE,
+        MAX_SPOILERS_PER_USER_VARIABLE, MAX_SPOILERS_VARIABLE, NOTIFY_DISCARDED_DRAFTS_VARIABLE,
+        OPERATOR_VARIABLE, REVEAL_LIMIT_VARIABLE, SECRET_VARIABLE, SETTINGS_FILE_VARIABLE,
+        SPOILER_LIMIT_VARIABLE, THUMBNAIL_URL_VARIABLE,
+    },
+    util::HOUR_IN_SECS,
+};
+
+/// Options the bot operator can set to tweak the bot's behaviour.
+pub(crate) struct Config {
+    /// A chat the bot forwards messages to before deleting them via `/spoiler`.
+    ///
+    /// When set, revealing such a spoiler forwards the stored copy instead of resending its
+    /// content, which keeps the original author visible. The copy is deleted again shortly
+    /// after the spoiler expires or is removed.
+    pub(crate) forward_chat: Option<ChatId>,
+
+    /// The time frame in which a tap on a spoiler button needs to follow the previous one, when a
+    /// spoiler requires several taps or a confirmation.
+    ///
+    /// Set in seconds, defaults to 10 seconds.
+    pub(crate) double_tap_window: Duration,
+
+    /// The key used to sign the [tokens] handed out in deep links and inline queries, and to
+    /// [seal the ids](crate::state::sealed::seal_id) of the spoilers users may post again.
+    ///
+    /// Defaults to a random key, which invalidates all tokens once the bot restarts.
+    ///
+    /// [tokens]: crate::state::token
+    pub(crate) secret: Vec<u8>,
+
+    /// How fast users may create, reveal and `/spoiler` spoilers, and how fast chats may `/spoiler`
+    /// them.
+    ///
+    /// Each limit is set as `actions/seconds`, see [`Limit`](crate::state::rate_limit::Limit).
+    pub(crate) rate_limits: RateLimits,
+
+    /// The user running the bot, who may see its statistics via `/stats`.
+    pub(crate) operator: Option<UserId>,
+
+    /// The maximum number of spoilers kept at once. Once reached, the oldest spoiler is removed.
+    ///
+    /// Defaults to 100 000.
+    pub(crate) max_spoilers: usize,
+
+    /// The maximum number of spoilers a single user may have created at once. Once reached, the
+    /// user's oldest spoiler is removed.
+    ///
+    /// Spoilers created in a private chat count, and so do spoilers created via inline query once
+    /// the user posted them, see [`State::claim_spoiler`]. Spoilers created in groups via
+    /// `/spoiler` do not count towards any user. Defaults to 100.
+    ///
+    /// [`State::claim_spoiler`]: crate::state::State::claim_spoiler
+    pub(crate) max_spoilers_per_user: usize,
+
+    /// The maximum number of unfinished spoilers kept at once. Once reached, the oldest draft is
+    /// discarded and its user is told so.
+    ///
+    /// Defaults to 10 000.
+    pub(crate) max_drafts: usize,
+
+    /// The time after which the spoiler creation of a user who does not proceed is cancelled.
+    ///
+    /// Set in seconds, defaults to 1 hour.
+    pub(crate) draft_timeout: Duration,
+
+    /// Whether users are told when their spoiler creation was cancelled due to the
+    /// [draft timeout](Config#structfield.draft_timeout).
+    ///
+    /// Set to `true` or `false`, defaults to `true`.
+    pub(crate) notify_discarded_drafts: bool,
+
+    /// Where the thumbnails of inline results are served from, see [`Config::thumbnail`].
+    ///
+    /// Inline results have no thumbnails if it is not set.
+    pub(crate) thumbnail_url: Option<Url>,
+
+    /// The file the settings, filters and hashtags of group chats are kept in, so they survive a
+    /// restart.
+    ///
+    /// Defaults to `chat_settings.json` in the working directory.
+    pub(crate) settings_file: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            forward_chat: None,
+            double_tap_window: Duration::from_secs(10),
+            secret: rand::thread_rng().gen::<[u8; 32]>().to_vec(),
+            rate_limits: RateLimits::default(),
+            operator: None,
+            max_spoilers: 100_000,
+            max_spoilers_per_user: 100,
+            max_drafts: 10_000,
+            draft_timeout: Duration::from_secs(HOUR_IN_SECS),
+            notify_discarded_drafts: true,
+            thumbnail_url: None,
+            settings_file: PathBuf::from("chat_settings.json"),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration from the environment.
+    ///
+    /// Options that are not set or cannot be parsed fall back to their default value.
+    pub(crate) fn from_env() -> Self {
+        let default = Config::default();
+
+        Config {
+            forward_chat: parse_variable(FORWARD_CHAT_VARIABLE).map(ChatId),
+            double_tap_window: parse_variable(DOUBLE_TAP_WINDOW_VARIABLE)
+                .map_or(default.double_tap_window, Duration::from_secs),
+            secret: env::var(SECRET_VARIABLE)
+                .ok()
+                .filter(|secret| !secret.is_empty())
+                .map_or(default.secret, String::into_bytes),
+            rate_limits: RateLimits {
+                create: parse_variable(CREATE_LIMIT_VARIABLE).unwrap_or(default.rate_limits.create),
+                reveal: parse_variable(REVEAL_LIMIT_VARIABLE).unwrap_or(default.rate_limits.reveal),
+                spoiler: parse_variable(SPOILER_LIMIT_VARIABLE)
+                    .unwrap_or(default.rate_limits.spoiler),
+                chat: parse_variable(CHAT_LIMIT_VARIABLE).unwrap_or(default.rate_limits.chat),
+            },
+            operator: parse_variable(OPERATOR_VARIABLE).map(UserId),
+            max_spoilers: parse_variable(MAX_SPOILERS_VARIABLE).unwrap_or(default.max_spoilers),
+            max_spoilers_per_user: parse_variable(MAX_SPOILERS_PER_USER_VARIABLE)
+                .unwrap_or(default.max_spoilers_per_user),
+            max_drafts: parse_variable(MAX_DRAFTS_VARIABLE).unwrap_or(default.max_drafts),
+            draft_timeout: parse_variable(DRAFT_TIMEOUT_VARIABLE)
+                .map_or(default.draft_timeout, Duration::from_secs),
+            notify_discarded_drafts: parse_variable(NOTIFY_DISCARDED_DRAFTS_VARIABLE)
+                .unwrap_or(default.notify_discarded_drafts),
+            thumbnail_url: parse_variable(THUMBNAIL_URL_VARIABLE),
+            settings_file: parse_variable(SETTINGS_FILE_VARIABLE).unwrap_or(default.settings_file),
+        }
+    }
+
+    /// Returns the URL of the thumbnail with the given name, e.g. `photo` for
+    /// `<thumbnail_url>/photo.png`.
+    ///
+    /// Returns `None` if no [thumbnail URL](Config#structfield.thumbnail_url) is configured.
+    pub(crate) fn thumbnail(&self, name: &str) -> Option<Url> {
+        let base = self.thumbnail_url.as_ref()?;
+        let url = format!("{}/{}.png", base.as_str().trim_end_matches('/'), name);
+
+        Url::parse(&url).ok()
+    }
+}
+
+/// Parses the value of the given environment variable, if it is set.
+fn parse_variable<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+}
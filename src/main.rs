@@ -1,72 +1,175 @@
 //! Spoilerowobot, a Telegram Bot for creating spoilers.
 #![warn(missing_docs)]
-#![warn(broken_intra_doc_links)]
+#![warn(rustdoc::broken_intra_doc_links)]
 
 #[macro_use]
 extern crate lazy_static;
 
-use tbot::predicates::{
-    chat::{is_group, is_private, is_supergroup},
-    without_state, PredicateBooleanOperations,
+use std::{env, sync::Arc};
+
+use teloxide::{
+    dispatching::{DpHandlerDescription, UpdateFilterExt},
+    dptree::Handler,
+    error_handlers::LoggingErrorHandler,
+    prelude::*,
+    types::Me,
+    update_listeners,
 };
 
 use crate::{
     bot::{
-        callback,
-        command::{cancel, help, spoiler, start},
+        auto_spoiler, callback,
+        command::{cancel, filter, hashtag, help, settings, spoiler, start, stats, Command},
         inline, spoiler_creation,
     },
-    state::{periodic, State},
+    config::Config,
+    state::{expiry, periodic, State},
 };
 
 mod bot;
+mod config;
 mod state;
 mod strings;
 mod util;
 
+/// The environment variable containing the token of the bot.
+static TOKEN_VARIABLE: &str = "SPOILEROWO_BOT_TOKEN";
+
+/// The handler every update is passed to.
+type UpdateHandler = Handler<'static, ResponseResult<()>, DpHandlerDescription>;
+
 #[tokio::main]
 async fn main() {
-    let bot = tbot::from_env!("SPOILEROWO_BOT_TOKEN");
-    let mut event_loop = bot.clone().stateful_event_loop(State::default());
+    let token = match env::var(TOKEN_VARIABLE) {
+        Ok(token) => token,
+        Err(e) => {
+            eprintln!("{} is not set: {}", TOKEN_VARIABLE, e);
+            std::process::exit(1);
+        }
+    };
+    let bot = Bot::new(token);
+
+    // The bot's identity is needed to link to it, so fetch it once before handling any update
+    let me = match bot.get_me().await {
+        Ok(me) if me.user.username.is_some() => me,
+        Ok(_) => {
+            eprintln!("The bot has no username");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Could not fetch the bot's identity: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    if let Err(msg) = event_loop.fetch_username().await {
-        dbg!(msg);
+    let (scheduler, expirations) = expiry::channel();
+    let state = Arc::new(State::new(Config::from_env(), &me, scheduler));
+    if let Err(e) = state.load_chat_settings() {
+        eprintln!(
+            "Could not read the chat settings from {}: {}",
+            state.config.settings_file.display(),
+            e
+        );
+        std::process::exit(1);
     }
 
-    // Listen to the following commands
-    event_loop.start(start::start_from_pm);
-    event_loop.command_if("spoiler", without_state(is_private), start::start_from_pm);
-    event_loop.command_if(
-        "spoiler",
-        without_state(is_group.or(is_supergroup)),
-        spoiler::spoiler,
-    );
-    event_loop.command("cancel", cancel::cancel);
-    event_loop.help(help::help);
-
-    // Listen to inline queries
-    event_loop.inline(inline::inline);
-
-    // Listen to data callbacks
-    event_loop.data_callback(callback::data_callback);
-
-    // Listen to the following events for spoiler creation
-    event_loop.animation_if(without_state(is_private), spoiler_creation::animation);
-    event_loop.audio_if(without_state(is_private), spoiler_creation::audio);
-    event_loop.contact_if(without_state(is_private), spoiler_creation::contact);
-    event_loop.dice_if(without_state(is_private), spoiler_creation::dice);
-    event_loop.document_if(without_state(is_private), spoiler_creation::document);
-    event_loop.location_if(without_state(is_private), spoiler_creation::location);
-    event_loop.photo_if(without_state(is_private), spoiler_creation::photo);
-    event_loop.sticker_if(without_state(is_private), spoiler_creation::sticker);
-    event_loop.text_if(without_state(is_private), spoiler_creation::text);
-    event_loop.video_if(without_state(is_private), spoiler_creation::video);
-    event_loop.video_note_if(without_state(is_private), spoiler_creation::video_note);
-    event_loop.voice_if(without_state(is_private), spoiler_creation::voice);
-
-    // A loop to check for expired spoilers that need to be cleared
-    tokio::spawn(periodic::poll_for_expired_entries(event_loop.get_state()));
+    // Remove spoilers once they expire, and clear other outdated entries periodically
+    tokio::spawn(periodic::remove_expired_spoilers(
+        expirations,
+        state.clone(),
+    ));
+    tokio::spawn(periodic::remove_expired_taps(state.clone()));
+    tokio::spawn(periodic::remove_expired_pages(state.clone()));
+    tokio::spawn(periodic::remove_expired_confirmations(state.clone()));
+    tokio::spawn(periodic::remove_expired_tokens(state.clone()));
+    tokio::spawn(periodic::remove_full_buckets(state.clone()));
+    tokio::spawn(periodic::discard_abandoned_drafts(
+        bot.clone(),
+        state.clone(),
+    ));
+    tokio::spawn(periodic::delete_stale_copies(bot.clone(), state.clone()));
 
     // todo webhooks?
-    event_loop.polling().start().await.unwrap();
+    let listener = update_listeners::polling_default(bot.clone()).await;
+    let result = Dispatcher::builder(bot, handler())
+        .dependencies(dptree::deps![state])
+        .build()
+        .try_dispatch_with_listener(
+            listener,
+            LoggingErrorHandler::with_custom_text("An error from the update listener"),
+        )
+        .await;
+    if let Err(e) = result {
+        eprintln!("Could not start dispatching updates: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Returns the handler that routes each update to the function handling it.
+fn handler() -> UpdateHandler {
+    let is_group = |message: Message| message.chat.is_group() || message.chat.is_supergroup();
+    let is_private = |message: Message| message.chat.is_private();
+
+    // Listen to the following commands, other commands are ignored
+    let commands = dptree::filter_map(|message: Message| Command::parse(&message))
+        .branch(command("start").endpoint(start::start_from_pm))
+        .branch(
+            command("spoiler")
+                .filter(is_private)
+                .endpoint(start::start_from_pm),
+        )
+        .branch(
+            command("spoiler")
+                .filter(is_group)
+                .endpoint(spoiler::spoiler),
+        )
+        .branch(
+            command("spoilersettings")
+                .filter(is_group)
+                .endpoint(settings::spoiler_settings),
+        )
+        .branch(
+            command("spoilerfilter")
+                .filter(is_group)
+                .endpoint(filter::spoiler_filter),
+        )
+        .branch(
+            command("spoilerhashtags")
+                .filter(is_group)
+                .endpoint(hashtag::spoiler_hashtags),
+        )
+        .branch(command("cancel").endpoint(cancel::cancel))
+        .branch(command("stats").filter(is_private).endpoint(stats::stats))
+        .branch(command("help").endpoint(help::help))
+        .endpoint(|| async { respond(()) });
+
+    // Listen to private messages for spoiler creation
+    let spoiler_creation = dptree::filter(is_private)
+        .branch(
+            dptree::filter(|message: Message| message.text().is_some())
+                .endpoint(spoiler_creation::text),
+        )
+        .endpoint(spoiler_creation::media);
+
+    // Listen to group messages to automatically spoiler them
+    let auto_spoiler = dptree::filter(is_group).endpoint(auto_spoiler::auto_spoiler);
+
+    dptree::entry()
+        .branch(
+            Update::filter_message()
+                .branch(commands)
+                .branch(spoiler_creation)
+                .branch(auto_spoiler),
+        )
+        // Listen to data callbacks
+        .branch(Update::filter_callback_query().endpoint(callback::data_callback))
+        // Listen to inline queries
+        .branch(Update::filter_inline_query().endpoint(inline::inline))
+        // Listen to posted inline results, if inline feedback is enabled
+        .branch(Update::filter_chosen_inline_result().endpoint(inline::chosen_inline_result))
+}
+
+/// Returns a filter that passes commands with the given name that are meant for this bot.
+fn command(name: &'static str) -> UpdateHandler {
+    dptree::filter(move |command: Command, me: Me| command.is(name, &me))
 }
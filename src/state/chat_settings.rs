@@ -0,0 +1,157 @@
+//! Per-chat settings that group admins can change.
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use crate::util::{DAY_IN_SECS, HOUR_IN_SECS, MONTH_IN_SECS, WEEK_IN_SECS, YEAR_IN_SECS};
+
+use super::{filter::Filter, reveal::RevealPolicy};
+
+/// The placeholder in a [title template] that gets replaced by the author's first name.
+///
+/// [title template]: ChatSettings#structfield.title_template
+pub(crate) static AUTHOR_PLACEHOLDER: &'static str = "{author}";
+
+/// The expiration times admins can choose from, along with how they are shown.
+pub(crate) static EXPIRATION_CHOICES: [(u64, &'static str); 5] = [
+    (HOUR_IN_SECS, "1 hour"),
+    (DAY_IN_SECS, "1 day"),
+    (WEEK_IN_SECS, "1 week"),
+    (MONTH_IN_SECS, "1 month"),
+    (YEAR_IN_SECS, "1 year"),
+];
+
+/// Settings of a single group chat.
+///
+/// Settings missing from the [settings file] take their default value.
+///
+/// [settings file]: crate::config::Config#structfield.settings_file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct ChatSettings {
+    /// Who may turn messages into spoilers via `/spoiler`.
+    pub(crate) moderation: ModerationPolicy,
+    /// Filters that automatically turn matching messages into spoilers.
+    pub(crate) filters: Vec<Filter>,
+    /// Hashtags that turn messages starting with them into spoilers. Stored in lowercase.
+    pub(crate) hashtags: Vec<String>,
+    /// The time after which spoilers posted in the group expire, unless specified otherwise.
+    pub(crate) default_expiration: Duration,
+    /// How spoilers posted in the group are revealed.
+    pub(crate) reveal: RevealPolicy,
+    /// The line describing a spoiled message. [`AUTHOR_PLACEHOLDER`] gets replaced by the name
+    /// of the message's author.
+    ///
+    /// It is also used as the title of spoilers that were not given one explicitly.
+    pub(crate) title_template: String,
+    /// Whether the original author of a spoiled message is shown.
+    pub(crate) show_author: bool,
+}
+
+impl ChatSettings {
+    /// Returns the line describing a message of the given author, if authors are shown at all.
+    pub(crate) fn attribution(&self, author: &str) -> Option<String> {
+        if self.show_author {
+            Some(self.title_template.replace(AUTHOR_PLACEHOLDER, author))
+        } else {
+            None
+        }
+    }
+
+    /// Returns how the default expiration time is shown.
+    pub(crate) fn default_expiration_name(&self) -> &'static str {
+        EXPIRATION_CHOICES
+            .iter()
+            .find(|(secs, _)| *secs == self.default_expiration.as_secs())
+            .map_or("custom", |(_, name)| name)
+    }
+
+    /// Switches to the next of the [`EXPIRATION_CHOICES`].
+    pub(crate) fn next_default_expiration(&mut self) {
+        let current = EXPIRATION_CHOICES
+            .iter()
+            .position(|(secs, _)| *secs == self.default_expiration.as_secs());
+        let next = current.map_or(0, |index| (index + 1) % EXPIRATION_CHOICES.len());
+        self.default_expiration = Duration::from_secs(EXPIRATION_CHOICES[next].0);
+    }
+}
+
+impl Default for ChatSettings {
+    fn default() -> Self {
+        ChatSettings {
+            moderation: ModerationPolicy::default(),
+            filters: Vec::new(),
+            hashtags: Vec::new(),
+            default_expiration: Duration::from_secs(DAY_IN_SECS),
+            reveal: RevealPolicy::default(),
+            title_template: format!("Bad message from {}:", AUTHOR_PLACEHOLDER),
+            show_author: true,
+        }
+    }
+}
+
+/// Decides who may turn messages into spoilers via `/spoiler`.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub(crate) enum ModerationPolicy {
+    /// Only group admins may use `/spoiler`.
+    AdminsOnly,
+    /// Group admins may spoiler any message, everyone else only their own ones.
+    #[default]
+    OwnMessages,
+    /// Anyone may spoiler any message.
+    Anyone,
+}
+
+impl ModerationPolicy {
+    /// All policies, in the order they are presented to the user.
+    pub(crate) const ALL: [ModerationPolicy; 3] = [
+        ModerationPolicy::AdminsOnly,
+        ModerationPolicy::OwnMessages,
+        ModerationPolicy::Anyone,
+    ];
+
+    /// The name users refer to this policy by.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ModerationPolicy::AdminsOnly => "admins",
+            ModerationPolicy::OwnMessages => "own",
+            ModerationPolicy::Anyone => "anyone",
+        }
+    }
+
+    /// A short explanation of this policy.
+    pub(crate) fn description(self) -> &'static str {
+        match self {
+            ModerationPolicy::AdminsOnly => "only admins may spoiler messages",
+            ModerationPolicy::OwnMessages => {
+                "admins may spoiler any message, everyone else only their own"
+            }
+            ModerationPolicy::Anyone => "anyone may spoiler any message",
+        }
+    }
+
+    /// Returns the policy with the given name.
+    pub(crate) fn from_name(name: &str) -> Option<ModerationPolicy> {
+        ModerationPolicy::ALL
+            .iter()
+            .copied()
+            .find(|policy| policy.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the policy that follows this one, wrapping around after the last one.
+    pub(crate) fn next(self) -> ModerationPolicy {
+        let index = ModerationPolicy::ALL
+            .iter()
+            .position(|policy| *policy == self)
+            .unwrap_or(0);
+        ModerationPolicy::ALL[(index + 1) % ModerationPolicy::ALL.len()]
+    }
+
+    /// Returns `true` if a user may spoiler a message under this policy.
+    pub(crate) fn allows(self, is_admin: bool, is_own_message: bool) -> bool {
+        match self {
+            ModerationPolicy::AdminsOnly => is_admin,
+            ModerationPolicy::OwnMessages => is_admin || is_own_message,
+            ModerationPolicy::Anyone => true,
+        }
+    }
+}
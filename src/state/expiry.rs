@@ -0,0 +1,238 @@
+//! Schedules the expiration of spoilers.
+//!
+//! The deadlines are kept by a single task that owns a [`DelayQueue`] and sleeps until either the
+//! next deadline is due or it is told about a change through its [`Scheduler`]. Spoilers therefore
+//! expire right at their deadline, without polling the queue in an interval.
+use std::{collections::HashMap, time::Duration};
+
+use futures_util::StreamExt;
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    time::Instant,
+};
+use tokio_util::time::{delay_queue, DelayQueue};
+
+/// The longest time an entry waits in the queue at once.
+///
+/// The queue does not take deadlines that are more than about two years ahead, so entries that
+/// expire later are queued again once this time has passed.
+const MAX_DELAY: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// A change to the deadlines sent to the [`Expirations`] task.
+#[derive(Debug)]
+enum Command {
+    /// Expires the entry with the given key at the given time.
+    Schedule(String, Instant),
+    /// Stops the entry with the given key from expiring.
+    Cancel(String),
+}
+
+/// A handle to schedule entries to expire.
+#[derive(Clone)]
+pub(crate) struct Scheduler {
+    /// Where the changes to the deadlines are sent to.
+    commands: UnboundedSender<Command>,
+}
+
+impl Scheduler {
+    /// Expires the entry with the given key at the given time.
+    ///
+    /// If the entry is scheduled already, its deadline is moved.
+    pub(crate) fn schedule(&self, key: String, deadline: Instant) {
+        self.send(Command::Schedule(key, deadline));
+    }
+
+    /// Stops the entry with the given key from expiring.
+    pub(crate) fn cancel(&self, key: String) {
+        self.send(Command::Cancel(key));
+    }
+
+    /// Sends the command to the [`Expirations`] task.
+    fn send(&self, command: Command) {
+        if let Err(e) = self.commands.send(command) {
+            dbg!("expirations are no longer running", e.0);
+        }
+    }
+}
+
+/// The task that keeps the deadlines of all scheduled entries.
+pub(crate) struct Expirations {
+    /// Changes to the deadlines sent by the [`Scheduler`]s.
+    commands: UnboundedReceiver<Command>,
+    /// A queue that yields the key of the entry that is going to expire next.
+    queue: DelayQueue<String>,
+    /// The position of each scheduled entry within the queue, along with its deadline.
+    positions: HashMap<String, (delay_queue::Key, Instant)>,
+}
+
+/// Creates a [`Scheduler`] along with the [`Expirations`] it is sending to.
+pub(crate) fn channel() -> (Scheduler, Expirations) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let expirations = Expirations {
+        commands: receiver,
+        queue: DelayQueue::new(),
+        positions: HashMap::new(),
+    };
+
+    (Scheduler { commands: sender }, expirations)
+}
+
+impl Expirations {
+    /// Calls `expire` with the key of each entry once its deadline is due.
+    ///
+    /// Runs until all [`Scheduler`]s are dropped.
+    pub(crate) async fn run(mut self, mut expire: impl FnMut(String)) {
+        loop {
+            tokio::select! {
+                command = self.commands.recv() => match command {
+                    Some(command) => self.apply(command),
+                    None => break,
+                },
+                Some(expired) = self.queue.next(), if !self.queue.is_empty() => {
+                    let key = expired.into_inner();
+                    match self.positions.remove(&key) {
+                        Some((_, deadline)) if deadline > Instant::now() => {
+                            self.apply(Command::Schedule(key, deadline));
+                        }
+                        _ => expire(key),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Changes the deadlines as requested.
+    fn apply(&mut self, command: Command) {
+        match command {
+            Command::Schedule(key, deadline) => {
+                let queued_until = deadline.min(Instant::now() + MAX_DELAY);
+                match self.positions.get_mut(&key) {
+                    Some((position, scheduled)) => {
+                        self.queue.reset_at(position, queued_until);
+                        *scheduled = deadline;
+                    }
+                    None => {
+                        let position = self.queue.insert_at(key.clone(), queued_until);
+                        self.positions.insert(key, (position, deadline));
+                    }
+                }
+            }
+            Command::Cancel(key) => {
+                if let Some((position, _)) = self.positions.remove(&key) {
+                    self.queue.remove(&position);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::task::JoinHandle;
+
+    use super::*;
+
+    /// Runs the expirations, collecting the keys of expired entries.
+    fn run() -> (Scheduler, Arc<Mutex<Vec<String>>>, JoinHandle<()>) {
+        let (scheduler, expirations) = channel();
+        let expired = Arc::new(Mutex::new(Vec::new()));
+        let task = tokio::spawn(expirations.run({
+            let expired = expired.clone();
+            move |key| expired.lock().unwrap().push(key)
+        }));
+
+        (scheduler, expired, task)
+    }
+
+    /// Lets the expirations task handle everything that is due by now.
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn entries_expire_at_their_deadline() {
+        let (scheduler, expired, _task) = run();
+        let start = Instant::now();
+        scheduler.schedule("later".to_string(), start + Duration::from_secs(120));
+        scheduler.schedule("sooner".to_string(), start + Duration::from_secs(60));
+
+        tokio::time::sleep_until(start + Duration::from_secs(59)).await;
+        settle().await;
+        assert!(expired.lock().unwrap().is_empty());
+
+        tokio::time::sleep_until(start + Duration::from_secs(60)).await;
+        settle().await;
+        assert_eq!(*expired.lock().unwrap(), ["sooner"]);
+
+        tokio::time::sleep_until(start + Duration::from_secs(120)).await;
+        settle().await;
+        assert_eq!(*expired.lock().unwrap(), ["sooner", "later"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn scheduling_again_moves_the_deadline() {
+        let (scheduler, expired, _task) = run();
+        let start = Instant::now();
+        scheduler.schedule("spoiler".to_string(), start + Duration::from_secs(60));
+        scheduler.schedule("spoiler".to_string(), start + Duration::from_secs(120));
+
+        tokio::time::sleep_until(start + Duration::from_secs(90)).await;
+        settle().await;
+        assert!(expired.lock().unwrap().is_empty());
+
+        tokio::time::sleep_until(start + Duration::from_secs(120)).await;
+        settle().await;
+        assert_eq!(*expired.lock().unwrap(), ["spoiler"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn entries_may_expire_years_ahead() {
+        let (scheduler, expired, _task) = run();
+        let start = Instant::now();
+        let deadline = start + Duration::from_secs(3 * 365 * 24 * 60 * 60);
+        scheduler.schedule("spoiler".to_string(), deadline);
+
+        // the timers of the runtime do not reach that far either, so time passes in steps
+        let step = Duration::from_secs(100 * 24 * 60 * 60);
+        while Instant::now() + step < deadline {
+            tokio::time::sleep(step).await;
+            settle().await;
+        }
+        tokio::time::sleep_until(deadline - Duration::from_secs(1)).await;
+        settle().await;
+        assert!(expired.lock().unwrap().is_empty());
+
+        tokio::time::sleep_until(deadline).await;
+        settle().await;
+        assert_eq!(*expired.lock().unwrap(), ["spoiler"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelled_entries_do_not_expire() {
+        let (scheduler, expired, _task) = run();
+        let start = Instant::now();
+        scheduler.schedule("spoiler".to_string(), start + Duration::from_secs(60));
+        scheduler.cancel("spoiler".to_string());
+        scheduler.cancel("unknown".to_string());
+
+        tokio::time::sleep_until(start + Duration::from_secs(600)).await;
+        settle().await;
+        assert!(expired.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stops_once_all_schedulers_are_dropped() {
+        let (scheduler, _expired, task) = run();
+        scheduler.schedule(
+            "spoiler".to_string(),
+            Instant::now() + Duration::from_secs(60),
+        );
+        drop(scheduler);
+
+        assert!(task.await.is_ok());
+    }
+}
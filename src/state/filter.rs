@@ -0,0 +1,147 @@
+//! Filters that automatically turn matching group messages into spoilers.
+use std::fmt::{self, Display, Formatter};
+
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio::time::Duration;
+
+/// A filter set up by the admins of a group.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Filter {
+    /// What a message needs to contain to match the filter.
+    pub(crate) pattern: Pattern,
+    /// The point in time after which the filter no longer applies, if any.
+    pub(crate) active_until: Option<DateTime<Utc>>,
+}
+
+/// What a message needs to contain to match a [`Filter`].
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum Pattern {
+    /// A keyword, matched case-insensitively anywhere in the message. Stored in lowercase.
+    Keyword(String),
+    /// A regular expression, matched case-insensitively.
+    #[serde(
+        serialize_with = "serialize_regex",
+        deserialize_with = "deserialize_regex"
+    )]
+    Regex(Regex),
+}
+
+impl Filter {
+    /// Creates a filter that matches messages containing the given keyword.
+    ///
+    /// The filter only applies for the given amount of time, if any.
+    pub(crate) fn keyword(keyword: &str, active_for: Option<Duration>) -> Self {
+        Filter {
+            pattern: Pattern::Keyword(keyword.to_lowercase()),
+            active_until: active_until(active_for),
+        }
+    }
+
+    /// Creates a filter that matches messages matching the given regular expression.
+    ///
+    /// The filter only applies for the given amount of time, if any.
+    pub(crate) fn regex(pattern: &str, active_for: Option<Duration>) -> Result<Self, regex::Error> {
+        Ok(Filter {
+            pattern: Pattern::Regex(RegexBuilder::new(pattern).case_insensitive(true).build()?),
+            active_until: active_until(active_for),
+        })
+    }
+
+    /// Returns `true` if the filter still applies.
+    pub(crate) fn is_active(&self) -> bool {
+        self.active_until.is_none_or(|until| Utc::now() < until)
+    }
+
+    /// Returns `true` if the filter applies and the text matches it.
+    pub(crate) fn matches(&self, text: &str) -> bool {
+        self.is_active()
+            && match &self.pattern {
+                Pattern::Keyword(keyword) => text.to_lowercase().contains(keyword),
+                Pattern::Regex(regex) => regex.is_match(text),
+            }
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match &self.pattern {
+            Pattern::Keyword(keyword) => write!(f, "keyword \"{}\"", keyword)?,
+            Pattern::Regex(regex) => write!(f, "regex \"{}\"", regex)?,
+        }
+        match self.active_until {
+            Some(until) => write!(f, " until {}", until.format("%F %R %Z")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns the point in time a filter that applies for the given amount of time expires at.
+fn active_until(active_for: Option<Duration>) -> Option<DateTime<Utc>> {
+    active_for
+        .and_then(|duration| chrono::Duration::from_std(duration).ok())
+        .and_then(|duration| Utc::now().checked_add_signed(duration))
+}
+
+/// Serializes the regular expression as its pattern.
+fn serialize_regex<S: Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(regex.as_str())
+}
+
+/// Deserializes a regular expression from its pattern, matching case-insensitively.
+fn deserialize_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_match_anywhere_ignoring_case() {
+        let filter = Filter::keyword("Dumbledore", None);
+
+        assert!(filter.is_active());
+        assert!(filter.matches("Snape kills DUMBLEDORE!"));
+        assert!(filter.matches("dumbledores"));
+        assert!(!filter.matches("Snape kills Albus"));
+    }
+
+    #[test]
+    fn regexes_match_ignoring_case() {
+        let filter = Filter::regex(r"\bep(isode)? ?\d+\b", None).unwrap();
+
+        assert!(filter.matches("What happened in EP 12?"));
+        assert!(filter.matches("episode3 was great"));
+        assert!(!filter.matches("epic"));
+        assert!(Filter::regex("(unclosed", None).is_err());
+    }
+
+    #[test]
+    fn filters_apply_for_the_given_time() {
+        let filter = Filter::keyword("dumbledore", Some(Duration::from_secs(60)));
+
+        assert!(filter.is_active());
+        assert!(filter.matches("Snape kills Dumbledore"));
+    }
+
+    #[test]
+    fn expired_filters_do_not_match() {
+        let expired = |pattern| Filter {
+            pattern,
+            active_until: Some(Utc::now() - chrono::Duration::seconds(1)),
+        };
+        let keyword = expired(Pattern::Keyword("dumbledore".to_string()));
+        let regex = expired(Pattern::Regex(Regex::new("dumbledore").unwrap()));
+
+        for filter in [keyword, regex] {
+            assert!(!filter.is_active());
+            assert!(!filter.matches("Snape kills dumbledore"));
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Counters of noteworthy events, for the bot operator to keep an eye on.
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::state::rate_limit::Action;
+
+/// Counts how often something happened since the bot started.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    /// How often creating a spoiler was refused due to rate limiting.
+    pub(crate) rate_limited_creations: AtomicU64,
+    /// How often revealing a spoiler was refused due to rate limiting.
+    pub(crate) rate_limited_reveals: AtomicU64,
+    /// How often `/spoiler` was refused due to rate limiting.
+    pub(crate) rate_limited_spoilers: AtomicU64,
+    /// How many spoilers were removed before expiring, to stay within the [total limit].
+    ///
+    /// [total limit]: crate::config::Config#structfield.max_spoilers
+    pub(crate) evicted_spoilers: AtomicU64,
+    /// How many spoilers were removed before expiring, to stay within the [limit per user].
+    ///
+    /// [limit per user]: crate::config::Config#structfield.max_spoilers_per_user
+    pub(crate) evicted_user_spoilers: AtomicU64,
+    /// How many unfinished spoilers were discarded, to stay within the [limit of drafts].
+    ///
+    /// [limit of drafts]: crate::config::Config#structfield.max_drafts
+    pub(crate) evicted_drafts: AtomicU64,
+}
+
+impl Metrics {
+    /// Counts an action that was refused due to rate limiting.
+    pub(crate) fn count_rate_limited(&self, action: Action) {
+        let counter = match action {
+            Action::Create => &self.rate_limited_creations,
+            Action::Reveal => &self.rate_limited_reveals,
+            Action::Spoiler => &self.rate_limited_spoilers,
+        };
+        Metrics::count(counter);
+    }
+
+    /// Adds one to a counter.
+    pub(crate) fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reads the current value of a counter.
+    pub(crate) fn get(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+}
@@ -1,104 +1,271 @@
 //! A State that get attached to the bot's event loop.
-use std::{
-    collections::HashMap,
-    ops::Deref,
-    sync::{Arc, Mutex},
-};
+use std::io;
 
-use tbot::types::user;
-use tokio::time::{delay_queue, DelayQueue, Duration};
+use chrono::Utc;
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use teloxide::types::{ChatId, Me, UserId};
+use tokio::time::{Duration, Instant};
 
-use crate::{state::spoiler::Content, strings::ERROR_NO_CONTENT, util};
+use crate::{config::Config, state::spoiler::Content, strings::ERROR_NO_CONTENT, util};
 
-use self::spoiler::{Spoiler, SpoilerCreationStatus};
+use self::{
+    chat_settings::ChatSettings,
+    expiry::Scheduler,
+    filter::Filter,
+    metrics::Metrics,
+    rate_limit::{Action, Bucket, Subject},
+    reveal::RevealPolicy,
+    spoiler::{Origin, Spoiler, SpoilerCreationStatus},
+    store::SpoilerStore,
+    token::{TokenError, TokenPurpose, TOKEN_LIFETIME_SECS},
+};
 
+pub(crate) mod chat_settings;
+pub(crate) mod expiry;
+pub(crate) mod filter;
+pub(crate) mod metrics;
 pub(crate) mod periodic;
+pub(crate) mod rate_limit;
+pub(crate) mod reveal;
+pub(crate) mod sealed;
+pub(crate) mod settings_file;
 pub(crate) mod spoiler;
+pub(crate) mod store;
+pub(crate) mod token;
+
+/// The time in seconds a user may take to turn to the next page of a long spoiler.
+pub(crate) static READING_TIME_SECS: u64 = 10 * 60;
+
+/// The time in seconds a user may take to type the confirmation word of a spoiler.
+pub(crate) static CONFIRMATION_TIME_SECS: u64 = 10 * 60;
 
 /// The bot's state.
 ///
 /// The state holds information about
 /// - users currently creating a new spoiler,
-/// - A map of all registered spoilers and
-/// - A queue that yields the next spoiler that is going to expire.
-#[derive(Default)]
+/// - users currently trying to reveal a spoiler,
+/// - A map of all registered spoilers,
+/// - A scheduler that expires spoilers once they are due and
+/// - Settings of group chats.
+///
+/// It also carries the bot's [`Config`].
+///
+/// Handlers run concurrently, so most of the state lives in [`DashMap`]s, which only lock the
+/// shard an entry is in. The registered spoilers and the index of who created them are changed
+/// together and therefore share a single lock, see [`SpoilerStore`]. No lock is ever held across
+/// an `.await`.
 pub(crate) struct State {
+    /// The bot's configuration.
+    pub(crate) config: Config,
+
+    /// Counters of noteworthy events.
+    pub(crate) metrics: Metrics,
+
+    /// The user id of the bot itself.
+    pub(crate) bot_id: UserId,
+
+    /// The username of the bot, without the leading `@`.
+    pub(crate) bot_username: String,
+
     /// A key-value store to track the progress of users creating a custom spoiler.
     ///
     /// Once a user starts the bot, a new key-value pair is inserted into the map.
     /// If a user cancels the operation or finishes creating their spoiler, the corresponding
-    /// key-value pair gets deleted again.
-    pub(self) creation_status: Arc<Mutex<HashMap<user::Id, SpoilerCreationStatus>>>,
+    /// key-value pair gets deleted again. So does it once the user did not proceed for the
+    /// configured [draft timeout], which is why the time of the last step is kept as well.
+    ///
+    /// [draft timeout]: crate::config::Config#structfield.draft_timeout
+    pub(self) creation_status: DashMap<UserId, (SpoilerCreationStatus, Instant)>,
+
+    /// A key-value store of spoilers that have not been fully created yet, along with the time
+    /// their content was sent.
+    pub(self) new_spoilers: DashMap<UserId, (Content, Instant)>,
+
+    /// A key-value store of users currently trying to open a spoiler that requires more than a
+    /// single tap, along with the time of their last tap and how many taps they made so far.
+    pub(self) pending_taps: DashMap<(UserId, String), (Instant, u8)>,
+
+    /// A key-value store of the page of a long spoiler each user is reading, along with the time
+    /// they last turned a page.
+    pub(self) open_pages: DashMap<(UserId, String), (Instant, usize)>,
+
+    /// A key-value store of users who were asked to type the confirmation word in order to see
+    /// the spoiler with the given id, along with the time they were asked.
+    pub(self) awaiting_confirmation: DashMap<UserId, (String, Instant)>,
+
+    /// A key-value store of single use tokens that have been used, along with the time they
+    /// were issued at.
+    pub(self) used_tokens: DashMap<String, i64>,
+
+    /// A key-value store of the rate limiting buckets of users and chats that took actions
+    /// recently.
+    pub(self) rate_limits: DashMap<(Action, Subject), Bucket>,
 
-    /// A key-value store of spoilers that have not been fully created yet.
-    pub(self) new_spoilers: Arc<Mutex<HashMap<user::Id, Content>>>,
+    /// All currently registered spoilers.
+    pub(self) spoilers: Mutex<SpoilerStore>,
 
-    /// A key-value store of users currently trying to open a major spoiler (which requires a double tap).
-    pub(self) open_major_spoiler: Arc<Mutex<HashMap<(user::Id, String), ()>>>,
+    /// Expires spoilers once they are due, by their lookup key.
+    pub(self) expirations: Scheduler,
 
-    /// A queue that holds information about which spoiler is going to expire next.
-    pub(self) expirations: Mutex<DelayQueue<String>>,
+    /// A key-value store of settings of group chats that differ from the defaults.
+    ///
+    /// It is kept in the [settings file], see [`State::load_chat_settings`].
+    ///
+    /// [settings file]: crate::config::Config#structfield.settings_file
+    pub(self) chat_settings: DashMap<ChatId, ChatSettings>,
 
-    /// A key-value store of all currently registered spoilers with information about when the entry shall be yielded back.
-    pub(self) spoilers: Mutex<HashMap<String, (Spoiler, delay_queue::Key)>>,
+    /// Held while writing the [settings file], so writes do not interleave.
+    ///
+    /// [settings file]: crate::config::Config#structfield.settings_file
+    pub(self) settings_file: Mutex<()>,
+
+    /// Copies in the [forward chat] of spoilers that are gone, which still need to be deleted.
+    ///
+    /// [forward chat]: crate::config::Config#structfield.forward_chat
+    pub(self) stale_copies: Mutex<Vec<Origin>>,
 }
 
 impl State {
+    /// Creates a new, empty state using the given configuration and the bot's identity, which
+    /// needs to be fetched once on startup.
+    ///
+    /// Spoilers are scheduled to expire with the given scheduler, whose [`Expirations`] need to
+    /// be run to actually remove them, see [`periodic::remove_expired_spoilers`].
+    ///
+    /// [`Expirations`]: expiry::Expirations
+    pub(crate) fn new(config: Config, me: &Me, expirations: Scheduler) -> Self {
+        State {
+            config,
+            metrics: Metrics::default(),
+            bot_id: me.id,
+            bot_username: me.username().to_string(),
+            creation_status: DashMap::default(),
+            new_spoilers: DashMap::default(),
+            pending_taps: DashMap::default(),
+            open_pages: DashMap::default(),
+            awaiting_confirmation: DashMap::default(),
+            used_tokens: DashMap::default(),
+            rate_limits: DashMap::default(),
+            spoilers: Mutex::default(),
+            expirations,
+            chat_settings: DashMap::default(),
+            settings_file: Mutex::default(),
+            stale_copies: Mutex::default(),
+        }
+    }
+
     /// Waits for the user to send a spoiler.
-    pub(crate) fn set_waiting_for_spoiler(&self, user: user::Id) -> Option<SpoilerCreationStatus> {
+    pub(crate) fn set_waiting_for_spoiler(&self, user: UserId) -> Option<SpoilerCreationStatus> {
         self.creation_status
-            .lock()
-            .unwrap()
-            .insert(user, SpoilerCreationStatus::WaitingForSpoiler)
+            .insert(
+                user,
+                (SpoilerCreationStatus::WaitingForSpoiler, Instant::now()),
+            )
+            .map(|(status, _)| status)
     }
 
     /// Waits for the user to send a title for the spoiler.
-    pub(crate) fn set_waiting_for_title(&self, user: user::Id) -> Option<SpoilerCreationStatus> {
+    pub(crate) fn set_waiting_for_title(&self, user: UserId) -> Option<SpoilerCreationStatus> {
         self.creation_status
-            .lock()
-            .unwrap()
-            .insert(user, SpoilerCreationStatus::WaitingForTitle)
+            .insert(
+                user,
+                (SpoilerCreationStatus::WaitingForTitle, Instant::now()),
+            )
+            .map(|(status, _)| status)
     }
 
-    /// Cancels the spoiler creation and remove the corresponding value from the state.
-    pub(crate) fn cancel_spoiler_creation(&self, user: &user::Id) -> Option<SpoilerCreationStatus> {
-        self.creation_status.lock().unwrap().remove(user)
+    /// Cancels the spoiler creation and remove the corresponding values from the state, including
+    /// content that has been sent already.
+    pub(crate) fn cancel_spoiler_creation(&self, user: &UserId) -> Option<SpoilerCreationStatus> {
+        self.new_spoilers.remove(user);
+        self.creation_status
+            .remove(user)
+            .map(|(_, (status, _))| status)
+    }
+
+    /// Cancels the spoiler creation of all users who did not proceed within the configured
+    /// [draft timeout].
+    ///
+    /// # Returns
+    /// The users whose spoiler creation was cancelled and who had sent content already, so their
+    /// draft got discarded.
+    ///
+    /// [draft timeout]: crate::config::Config#structfield.draft_timeout
+    pub(crate) fn discard_abandoned_drafts(&self) -> Vec<UserId> {
+        let timeout = self.config.draft_timeout;
+        let abandoned: Vec<UserId> = self
+            .creation_status
+            .iter()
+            .filter(|entry| entry.1.elapsed() > timeout)
+            .map(|entry| *entry.key())
+            .collect();
+
+        // users may have proceeded in the meantime, so check again while removing
+        abandoned
+            .into_iter()
+            .filter(|user| {
+                let removed = self
+                    .creation_status
+                    .remove_if(user, |_, (_, last_step)| last_step.elapsed() > timeout);
+                removed.is_some() && self.new_spoilers.remove(user).is_some()
+            })
+            .collect()
     }
 
     /// Returns `true` if the bot is waiting for the user to specify a title.
-    pub(crate) fn waiting_for_title(&self, user: &user::Id) -> bool {
-        match self.creation_status.lock().unwrap().get(user) {
-            Some(state) => state.eq(&SpoilerCreationStatus::WaitingForTitle),
+    pub(crate) fn waiting_for_title(&self, user: &UserId) -> bool {
+        match self.creation_status.get(user) {
+            Some(entry) => entry.0.eq(&SpoilerCreationStatus::WaitingForTitle),
             None => false,
         }
     }
 
     /// Returns `true` if the bot is waiting for the content to be spoiled.
-    pub(crate) fn waiting_for_spoiler(&self, user: &user::Id) -> bool {
-        match self.creation_status.lock().unwrap().get(user) {
-            Some(state) => state.eq(&SpoilerCreationStatus::WaitingForSpoiler),
+    pub(crate) fn waiting_for_spoiler(&self, user: &UserId) -> bool {
+        match self.creation_status.get(user) {
+            Some(entry) => entry.0.eq(&SpoilerCreationStatus::WaitingForSpoiler),
             None => false,
         }
     }
 
     /// Creates a new Spoiler and add it to the state.
-    pub(crate) fn new_spoiler(&self, user: user::Id, content: Content) {
-        self.new_spoilers.lock().unwrap().insert(user, content);
+    ///
+    /// If there are too many [drafts] already, the oldest one is discarded and the spoiler
+    /// creation of its user is cancelled.
+    ///
+    /// # Returns
+    /// The user whose draft got discarded, if any, so they can be told about it.
+    ///
+    /// [drafts]: crate::config::Config#structfield.max_drafts
+    pub(crate) fn new_spoiler(&self, user: UserId, content: Content) -> Option<UserId> {
+        let mut evicted = None;
+        if !self.new_spoilers.contains_key(&user)
+            && self.new_spoilers.len() >= self.config.max_drafts
+        {
+            let oldest = self
+                .new_spoilers
+                .iter()
+                .min_by_key(|entry| entry.1)
+                .map(|entry| *entry.key());
+            if let Some(oldest) = oldest {
+                self.new_spoilers.remove(&oldest);
+                self.creation_status.remove(&oldest);
+                Metrics::count(&self.metrics.evicted_drafts);
+                evicted = Some(oldest);
+            }
+        }
+        self.new_spoilers.insert(user, (content, Instant::now()));
+
+        evicted
     }
 
     /// Gets the title of the requested spoiler
-    pub(crate) fn get_spoiler_title(&self, spoiler_id: &String) -> Option<String> {
-        match self.spoilers.lock().unwrap().get(spoiler_id) {
-            Some(spoiler) => Some(
-                spoiler
-                    .0
-                    .title
-                    .as_ref()
-                    .unwrap_or(&"".to_string())
-                    .to_owned(),
-            ),
-            None => None,
-        }
+    pub(crate) fn get_spoiler_title(&self, spoiler_id: &str) -> Option<String> {
+        self.spoilers
+            .lock()
+            .get(&sealed::lookup_key(spoiler_id))
+            .map(|spoiler| spoiler.title.clone().unwrap_or_default())
     }
 
     /// Sets the title of the spoiler and the time after which it should expire.
@@ -108,70 +275,936 @@ impl State {
     /// The default expiration time set to 1 day.
     ///
     /// # Returns
-    /// The id of the newly created spoiler.
+    /// The id of the newly created spoiler, or `None` if the user's draft is gone, e.g. since it
+    /// was discarded in the meantime.
     pub(crate) fn set_spoiler_title_and_expiration(
         &self,
-        user_id: user::Id,
+        user_id: UserId,
         title: String,
         expires_in: Option<Duration>,
-    ) -> String {
+    ) -> Option<String> {
         let title = util::strip_expiration_suffix(&title);
         let title = if title.eq("-") { None } else { Some(title) };
 
-        let spoiler_id = util::random_id();
-
-        let content = { self.new_spoilers.lock().unwrap().remove(&user_id) };
-        match content {
-            Some(content) => {
-                let spoiler = Spoiler::new(spoiler_id.to_owned(), title, content, expires_in);
-                self.add_spoiler_to_queue(spoiler);
+        // the creation process is over either way
+        self.creation_status.remove(&user_id);
+        match self.new_spoilers.remove(&user_id) {
+            Some((_, (content, _))) => {
+                Some(self.create_spoiler(title, content, expires_in, Some(user_id)))
             }
             None => {
                 dbg!(ERROR_NO_CONTENT);
+                None
             }
         }
+    }
+
+    /// Creates a spoiler from the given content right away, without going through the
+    /// creation process.
+    ///
+    /// The spoiler gets a random id that is not used by any other spoiler yet.
+    ///
+    /// If there are too many spoilers, in total or of the given creator, the oldest one gets
+    /// removed before it expires, see [`max_spoilers`] and [`max_spoilers_per_user`].
+    ///
+    /// The creator can post the spoiler again from their [recent spoilers].
+    ///
+    /// # Returns
+    /// The id of the newly created spoiler.
+    ///
+    /// [`max_spoilers`]: crate::config::Config#structfield.max_spoilers
+    /// [`max_spoilers_per_user`]: crate::config::Config#structfield.max_spoilers_per_user
+    /// [recent spoilers]: State::recent_spoilers
+    pub(crate) fn create_spoiler(
+        &self,
+        title: Option<String>,
+        content: Content,
+        expires_in: Option<Duration>,
+        creator: Option<UserId>,
+    ) -> String {
+        // keep the spoilers locked until the new one is inserted, so no one else can take the id
+        let mut spoilers = self.spoilers.lock();
+
+        if let Some(creator) = creator {
+            self.make_room_for(&mut spoilers, &creator);
+        }
+        while spoilers.len() >= self.config.max_spoilers.max(1) {
+            match spoilers.oldest() {
+                Some(oldest) => {
+                    self.forget_copy(spoilers.remove(&oldest));
+                    self.expirations.cancel(oldest);
+                    Metrics::count(&self.metrics.evicted_spoilers);
+                }
+                None => break,
+            }
+        }
+
+        let mut spoiler_id = util::random_id();
+        while spoilers.get(&sealed::lookup_key(&spoiler_id)).is_some() {
+            dbg!("spoiler id collision");
+            spoiler_id = util::random_id();
+        }
 
-        return spoiler_id;
+        // the id itself is not stored, so the content can only be opened by whoever knows it
+        let content = content.seal(&spoiler_id);
+        let mut spoiler = Spoiler::new(
+            sealed::lookup_key(&spoiler_id),
+            title,
+            content,
+            expires_in,
+            creator,
+        );
+        spoiler.sealed_id =
+            creator.map(|creator| sealed::seal_id(&self.config.secret, creator, &spoiler_id));
+        self.expirations
+            .schedule(spoiler.id.clone(), spoiler.created_at + spoiler.expires_in);
+        spoilers.insert(spoiler);
+
+        spoiler_id
     }
 
-    /// Returns the spoiler by the specified spoiler id.
-    pub(crate) fn get_spoiler(&self, id: &String) -> Option<Spoiler> {
-        match self.spoilers.lock().unwrap().get(id) {
-            Some(val) => Some(val.deref().0.clone()),
-            None => None,
+    /// Removes the oldest spoilers of the given creator until they may have another one, see
+    /// [`max_spoilers_per_user`].
+    ///
+    /// [`max_spoilers_per_user`]: crate::config::Config#structfield.max_spoilers_per_user
+    fn make_room_for(&self, spoilers: &mut SpoilerStore, creator: &UserId) {
+        while spoilers.count_by(creator) >= self.config.max_spoilers_per_user.max(1) {
+            match spoilers.oldest_by(creator) {
+                Some(oldest) => {
+                    self.forget_copy(spoilers.remove(&oldest));
+                    self.expirations.cancel(oldest);
+                    Metrics::count(&self.metrics.evicted_user_spoilers);
+                }
+                None => break,
+            }
         }
     }
 
-    /// Return true if the user needs to tap once more to the spoiler button
+    /// Attributes a spoiler the user posted via inline query to them, so it shows up among their
+    /// [recent spoilers], and remembers the policy it was posted with, if any.
     ///
-    /// This internally checks how often the user has tried to open a given spoiler.
-    pub(crate) fn needs_to_tap_once_more(&self, user: &user::Id, spoiler_id: &String) -> bool {
-        let mut open_major_spoiler = self.open_major_spoiler.lock().unwrap();
+    /// Every keystroke of an inline query creates a spoiler, so those are only attributed once
+    /// they are actually posted. Spoilers of other creators stay untouched.
+    ///
+    /// [recent spoilers]: State::recent_spoilers
+    pub(crate) fn claim_spoiler(
+        &self,
+        spoiler_id: &str,
+        user: UserId,
+        policy: Option<RevealPolicy>,
+    ) {
+        let lookup_key = sealed::lookup_key(spoiler_id);
+        let mut spoilers = self.spoilers.lock();
 
-        match open_major_spoiler.remove(&(user.clone(), spoiler_id.clone())) {
-            Some(()) => false,
-            None => {
-                open_major_spoiler.insert((*user, spoiler_id.clone()), ());
-                true
+        let unattributed = match spoilers.get(&lookup_key) {
+            Some(spoiler) => spoiler.creator.is_none(),
+            None => return,
+        };
+        if unattributed {
+            self.make_room_for(&mut spoilers, &user);
+            spoilers.attribute(&lookup_key, user);
+        }
+        if let Some(spoiler) = spoilers.get_mut(&lookup_key) {
+            if spoiler.creator != Some(user) {
+                return;
+            }
+            if unattributed {
+                spoiler.sealed_id = Some(sealed::seal_id(&self.config.secret, user, spoiler_id));
+            }
+            if policy.is_some() {
+                spoiler.shared_policy = policy;
             }
         }
     }
 
-    /// Add a Spoiler to the DelayQueue
-    fn add_spoiler_to_queue(&self, spoiler: Spoiler) {
-        let delay_key;
-        {
-            delay_key = self
-                .expirations
-                .lock()
-                .unwrap()
-                .insert(spoiler.id.clone(), spoiler.expires_in);
+    /// Returns the spoiler by the specified spoiler id, with its content opened.
+    pub(crate) fn get_spoiler(&self, id: &str) -> Option<Spoiler> {
+        let spoiler = self.spoilers.lock().get(&sealed::lookup_key(id))?.clone();
+
+        Some(Spoiler {
+            id: id.to_string(),
+            content: spoiler.content.open(id)?,
+            ..spoiler
+        })
+    }
+
+    /// Returns up to `limit` spoilers the given user created in a private chat or posted via
+    /// inline query that did not expire yet, newest first.
+    ///
+    /// The spoilers are opened with the id [sealed for their creator](Spoiler#structfield.sealed_id),
+    /// so they can be posted again.
+    pub(crate) fn recent_spoilers(&self, creator: UserId, limit: usize) -> Vec<Spoiler> {
+        let recent: Vec<Spoiler> = {
+            let spoilers = self.spoilers.lock();
+            spoilers
+                .created_by(&creator)
+                .filter_map(|lookup_key| spoilers.get(lookup_key))
+                .filter(|spoiler| spoiler.created_at + spoiler.expires_in > Instant::now())
+                .take(limit)
+                .cloned()
+                .collect()
+        };
+
+        // opening them takes a while, so other handlers are not kept waiting for the lock
+        recent
+            .into_iter()
+            .filter_map(|spoiler| {
+                let sealed_id = spoiler.sealed_id.as_deref()?;
+                let id = sealed::open_id(&self.config.secret, creator, sealed_id)?;
+
+                Some(Spoiler {
+                    content: spoiler.content.clone().open(&id)?,
+                    id,
+                    ..spoiler
+                })
+            })
+            .collect()
+    }
+
+    /// Removes the spoiler stored under the given lookup key, e.g. once it expired.
+    pub(crate) fn remove_spoiler(&self, lookup_key: &str) {
+        let removed = self.spoilers.lock().remove(lookup_key);
+        self.forget_copy(removed);
+    }
+
+    /// Removes the spoiler with the given id right away, e.g. if it could not be posted.
+    pub(crate) fn discard_spoiler(&self, spoiler_id: &str) {
+        let lookup_key = sealed::lookup_key(spoiler_id);
+        let removed = self.spoilers.lock().remove(&lookup_key);
+        self.forget_copy(removed);
+        self.expirations.cancel(lookup_key);
+    }
+
+    /// Remembers to delete the copy of a removed spoiler's original message, if there is one.
+    fn forget_copy(&self, removed: Option<Spoiler>) {
+        if let Some(origin) = removed.and_then(|spoiler| spoiler.origin) {
+            self.stale_copies.lock().push(origin);
+        }
+    }
+
+    /// Takes the copies of original messages whose spoiler is gone, so they can be deleted.
+    pub(crate) fn take_stale_copies(&self) -> Vec<Origin> {
+        std::mem::take(&mut *self.stale_copies.lock())
+    }
+
+    /// Returns the number of spoilers currently registered.
+    pub(crate) fn spoiler_count(&self) -> usize {
+        self.spoilers.lock().len()
+    }
+
+    /// Remembers where a copy of the spoiler's original message can be found.
+    pub(crate) fn set_spoiler_origin(&self, spoiler_id: &str, origin: Origin) {
+        let lookup_key = sealed::lookup_key(spoiler_id);
+        if let Some(spoiler) = self.spoilers.lock().get_mut(&lookup_key) {
+            spoiler.origin = Some(origin);
+        }
+    }
+
+    /// Fixes how the spoiler is revealed, regardless of the buttons it is posted with.
+    pub(crate) fn set_spoiler_policy(&self, spoiler_id: &str, policy: RevealPolicy) {
+        let lookup_key = sealed::lookup_key(spoiler_id);
+        if let Some(spoiler) = self.spoilers.lock().get_mut(&lookup_key) {
+            spoiler.policy = Some(policy);
         }
+    }
+
+    /// Counts a tap of the user on the spoiler button and returns how many more taps it takes
+    /// until the spoiler is revealed.
+    ///
+    /// A tap only adds to the previous ones if it follows the last tap within the configured
+    /// [double tap window], otherwise counting starts over.
+    ///
+    /// [double tap window]: crate::config::Config#structfield.double_tap_window
+    pub(crate) fn remaining_taps(&self, user: &UserId, spoiler_id: &str, required: u8) -> u8 {
+        let key = (*user, spoiler_id.to_string());
+        let now = Instant::now();
+
+        let taps = match self.pending_taps.remove(&key) {
+            Some((_, (last_tap, taps)))
+                if now.duration_since(last_tap) <= self.config.double_tap_window =>
+            {
+                taps + 1
+            }
+            _ => 1,
+        };
+        if taps >= required {
+            0
+        } else {
+            self.pending_taps.insert(key, (now, taps));
+            required - taps
+        }
+    }
+
+    /// Remembers that the user tapped the button of a spoiler that needs to be confirmed.
+    pub(crate) fn register_tap(&self, user: UserId, spoiler_id: &str) {
+        self.pending_taps
+            .insert((user, spoiler_id.to_string()), (Instant::now(), 1));
+    }
+
+    /// Returns `true` if the user tapped the spoiler button within the [double tap window] before
+    /// confirming.
+    ///
+    /// [double tap window]: crate::config::Config#structfield.double_tap_window
+    pub(crate) fn confirm_tap(&self, user: &UserId, spoiler_id: &str) -> bool {
+        match self.pending_taps.remove(&(*user, spoiler_id.to_string())) {
+            Some((_, (last_tap, _))) => last_tap.elapsed() <= self.config.double_tap_window,
+            None => false,
+        }
+    }
+
+    /// Forgets about taps on spoilers that are older than the double tap window.
+    pub(crate) fn remove_expired_taps(&self) {
+        let window = self.config.double_tap_window;
+        self.pending_taps
+            .retain(|_, (last_tap, _)| last_tap.elapsed() <= window);
+    }
+
+    /// Remembers that the user revealed the first page of a long spoiler.
+    pub(crate) fn open_first_page(&self, user: UserId, spoiler_id: &str) {
+        self.open_pages
+            .insert((user, spoiler_id.to_string()), (Instant::now(), 0));
+    }
+
+    /// Turns to the next of the given number of pages of the spoiler the user is reading, starting
+    /// over after the last one, and returns its index.
+    ///
+    /// Returns `None` if the user did not reveal the spoiler within the [reading time].
+    ///
+    /// [reading time]: READING_TIME_SECS
+    pub(crate) fn next_page(&self, user: &UserId, spoiler_id: &str, pages: usize) -> Option<usize> {
+        let mut entry = self.open_pages.get_mut(&(*user, spoiler_id.to_string()))?;
+        let (last_turned, page) = entry.value_mut();
+        if pages == 0 || last_turned.elapsed() > Duration::from_secs(READING_TIME_SECS) {
+            return None;
+        }
+
+        *last_turned = Instant::now();
+        *page = (*page + 1) % pages;
+        Some(*page)
+    }
+
+    /// Forgets about long spoilers users stopped reading.
+    pub(crate) fn remove_expired_pages(&self) {
+        let reading_time = Duration::from_secs(READING_TIME_SECS);
+        self.open_pages
+            .retain(|_, (last_turned, _)| last_turned.elapsed() <= reading_time);
+    }
+
+    /// Waits for the user to type the confirmation word for the given spoiler.
+    pub(crate) fn await_confirmation_word(&self, user: UserId, spoiler_id: String) {
+        self.awaiting_confirmation
+            .insert(user, (spoiler_id, Instant::now()));
+    }
+
+    /// Stops waiting for the user to type a confirmation word and returns the id of the spoiler
+    /// it was meant for.
+    ///
+    /// Returns `None` if the user was asked longer than the [confirmation time] ago, so later
+    /// messages are not mistaken for an answer.
+    ///
+    /// [confirmation time]: CONFIRMATION_TIME_SECS
+    pub(crate) fn take_awaited_confirmation(&self, user: &UserId) -> Option<String> {
+        let confirmation_time = Duration::from_secs(CONFIRMATION_TIME_SECS);
+        self.awaiting_confirmation
+            .remove(user)
+            .filter(|(_, (_, asked))| asked.elapsed() <= confirmation_time)
+            .map(|(_, (spoiler_id, _))| spoiler_id)
+    }
+
+    /// Stops waiting for confirmation words users did not type in time.
+    pub(crate) fn remove_expired_confirmations(&self) {
+        let confirmation_time = Duration::from_secs(CONFIRMATION_TIME_SECS);
+        self.awaiting_confirmation
+            .retain(|_, (_, asked)| asked.elapsed() <= confirmation_time);
+    }
+
+    /// Issues a [token](token) granting the user access to the given spoiler.
+    pub(crate) fn issue_token(
+        &self,
+        purpose: TokenPurpose,
+        spoiler_id: &str,
+        user: UserId,
+    ) -> String {
+        token::issue(&self.config.secret, purpose, spoiler_id, user)
+    }
+
+    /// Checks a [token](token) presented by the user and returns the id of the spoiler it grants
+    /// access to.
+    ///
+    /// [Single use](TokenPurpose::single_use) tokens are rejected once they have been checked
+    /// before.
+    pub(crate) fn check_token(
+        &self,
+        purpose: TokenPurpose,
+        token: &str,
+        user: UserId,
+    ) -> Result<String, TokenError> {
+        let verified = token::verify(&self.config.secret, purpose, token, user)?;
+        if purpose.single_use()
+            && self
+                .used_tokens
+                .insert(token.to_string(), verified.issued)
+                .is_some()
         {
-            self.spoilers
-                .lock()
-                .unwrap()
-                .insert(spoiler.id.clone(), (spoiler, delay_key));
+            return Err(TokenError::Reused);
+        }
+
+        Ok(verified.spoiler_id)
+    }
+
+    /// Forgets about used tokens that have expired anyway.
+    pub(crate) fn remove_expired_tokens(&self) {
+        let now = Utc::now().timestamp();
+        self.used_tokens
+            .retain(|_, issued| now - *issued <= TOKEN_LIFETIME_SECS);
+    }
+
+    /// Returns `true` if all of the given subjects may take the action right now.
+    ///
+    /// Each subject has to have a token left, otherwise none is taken. Refused actions are
+    /// logged and counted in the [metrics](State#structfield.metrics).
+    pub(crate) fn allow(&self, action: Action, subjects: &[Subject]) -> bool {
+        let limits = self.config.rate_limits;
+
+        let mut taken = Vec::with_capacity(subjects.len());
+        for subject in subjects {
+            let limit = limits.limit(action, *subject);
+            // the bucket is released right away, so other subjects can be handled safely
+            let took = self
+                .rate_limits
+                .entry((action, *subject))
+                .or_insert_with(|| Bucket::full(limit))
+                .take(limit);
+
+            if !took {
+                // give back the tokens already taken from the other subjects
+                for subject in taken {
+                    if let Some(mut bucket) = self.rate_limits.get_mut(&(action, subject)) {
+                        bucket.give_back(limits.limit(action, subject));
+                    }
+                }
+                dbg!("rate limited", action, subject);
+                self.metrics.count_rate_limited(action);
+                return false;
+            }
+            taken.push(*subject);
+        }
+
+        true
+    }
+
+    /// Forgets about rate limiting buckets that have been refilled completely.
+    pub(crate) fn remove_full_buckets(&self) {
+        let limits = self.config.rate_limits;
+        self.rate_limits
+            .retain(|(action, subject), bucket| !bucket.is_full(limits.limit(*action, *subject)));
+    }
+
+    /// Reads the settings of all chats from the [settings file].
+    ///
+    /// [settings file]: crate::config::Config#structfield.settings_file
+    pub(crate) fn load_chat_settings(&self) -> io::Result<()> {
+        for (chat, settings) in settings_file::load(&self.config.settings_file)? {
+            self.chat_settings.insert(chat, settings);
         }
+        Ok(())
+    }
+
+    /// Writes the settings of all chats to the [settings file].
+    ///
+    /// Must not be called while holding a reference into
+    /// [`chat_settings`](State#structfield.chat_settings).
+    ///
+    /// [settings file]: crate::config::Config#structfield.settings_file
+    fn save_chat_settings(&self) {
+        let _lock = self.settings_file.lock();
+        let settings = self
+            .chat_settings
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()));
+
+        if let Err(e) = settings_file::save(&self.config.settings_file, settings) {
+            dbg!(e);
+        }
+    }
+
+    /// Returns the settings of the given chat.
+    pub(crate) fn get_chat_settings(&self, chat: &ChatId) -> ChatSettings {
+        self.chat_settings
+            .get(chat)
+            .map(|settings| settings.clone())
+            .unwrap_or_default()
+    }
+
+    /// Changes the settings of the given chat and returns the changed settings.
+    pub(crate) fn update_chat_settings(
+        &self,
+        chat: ChatId,
+        update: impl FnOnce(&mut ChatSettings),
+    ) -> ChatSettings {
+        let settings = {
+            let mut settings = self.chat_settings.entry(chat).or_default();
+            update(&mut settings);
+            settings.clone()
+        };
+        self.save_chat_settings();
+        settings
+    }
+
+    /// Adds a spoiler filter to the given chat.
+    pub(crate) fn add_filter(&self, chat: ChatId, filter: Filter) {
+        self.chat_settings
+            .entry(chat)
+            .or_default()
+            .filters
+            .push(filter);
+        self.save_chat_settings();
+    }
+
+    /// Removes the spoiler filter at the given index from the given chat.
+    pub(crate) fn remove_filter(&self, chat: &ChatId, index: usize) -> Option<Filter> {
+        let removed = match self.chat_settings.get_mut(chat) {
+            Some(mut settings) if index < settings.filters.len() => {
+                Some(settings.filters.remove(index))
+            }
+            _ => None,
+        };
+        if removed.is_some() {
+            self.save_chat_settings();
+        }
+        removed
+    }
+
+    /// Returns `true` if the text matches any of the spoiler filters of the given chat.
+    ///
+    /// Filters that no longer apply are removed along the way.
+    pub(crate) fn matches_filter(&self, chat: &ChatId, text: &str) -> bool {
+        match self.chat_settings.get_mut(chat) {
+            Some(mut settings) => {
+                settings.filters.retain(Filter::is_active);
+                settings.filters.iter().any(|filter| filter.matches(text))
+            }
+            None => false,
+        }
+    }
+
+    /// Adds a spoiler hashtag to the given chat.
+    ///
+    /// Returns `false` if the chat already had that hashtag.
+    pub(crate) fn add_hashtag(&self, chat: ChatId, hashtag: &str) -> bool {
+        let hashtag = hashtag.to_lowercase();
+        let added = {
+            let mut settings = self.chat_settings.entry(chat).or_default();
+            let hashtags = &mut settings.hashtags;
+
+            if hashtags.contains(&hashtag) {
+                false
+            } else {
+                hashtags.push(hashtag);
+                true
+            }
+        };
+        if added {
+            self.save_chat_settings();
+        }
+        added
+    }
+
+    /// Removes a spoiler hashtag from the given chat.
+    ///
+    /// Returns `false` if the chat did not have that hashtag.
+    pub(crate) fn remove_hashtag(&self, chat: &ChatId, hashtag: &str) -> bool {
+        let hashtag = hashtag.to_lowercase();
+        let removed = match self.chat_settings.get_mut(chat) {
+            Some(mut settings) => {
+                let count = settings.hashtags.len();
+                settings.hashtags.retain(|h| h.ne(&hashtag));
+                count != settings.hashtags.len()
+            }
+            None => false,
+        };
+        if removed {
+            self.save_chat_settings();
+        }
+        removed
+    }
+
+    /// Returns the spoiler hashtag of the given chat the text starts with, if any.
+    pub(crate) fn leading_hashtag(&self, chat: &ChatId, text: &str) -> Option<String> {
+        let first_word = text.split_whitespace().next()?.to_lowercase();
+        match self.chat_settings.get(chat) {
+            Some(settings) if settings.hashtags.contains(&first_word) => Some(first_word),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU64, Ordering},
+        thread,
+    };
+
+    use super::*;
+    use crate::bot::recorded::state;
+
+    use self::rate_limit::{Limit, RateLimits};
+
+    #[test]
+    fn stored_spoilers_are_unreadable_without_their_id() {
+        let (state, _expirations) = state(Config::default());
+        let creator = UserId(42);
+        let id = state.create_spoiler(
+            Some("Harry Potter".to_string()),
+            Content::Text("Snape kills Dumbledore".to_string()),
+            None,
+            Some(creator),
+        );
+
+        let stored = state.spoilers.lock().get(&sealed::lookup_key(&id)).cloned();
+        let stored = stored.unwrap();
+        assert_ne!(stored.id, id);
+        assert!(!stored.id.contains(&id));
+        assert!(!stored.sealed_id.unwrap().contains(&id));
+        match &stored.content {
+            Content::Text(text) => assert!(!text.contains("Dumbledore")),
+            _ => panic!("the kind of content changed"),
+        }
+        assert!(state.get_spoiler(&stored.id).is_none());
+
+        match state.get_spoiler(&id).map(|spoiler| spoiler.content) {
+            Some(Content::Text(text)) => assert_eq!(text, "Snape kills Dumbledore"),
+            _ => panic!("the spoiler cannot be opened with its id"),
+        }
+    }
+
+    #[test]
+    fn posted_inline_spoilers_become_recent_spoilers() {
+        let (state, _expirations) = state(Config {
+            max_spoilers_per_user: 2,
+            ..Config::default()
+        });
+        let (user, other) = (UserId(42), UserId(43));
+        let text = |text: &str| Content::String(text.to_string());
+
+        let typed = state.create_spoiler(None, text("Snape"), None, None);
+        let posted = state.create_spoiler(None, text("Snape kills Dumbledore"), None, None);
+        assert!(state.recent_spoilers(user, 10).is_empty());
+
+        state.claim_spoiler(&posted, user, Some(RevealPolicy::Confirm));
+        // only the first user to post a spoiler claims it
+        state.claim_spoiler(&posted, other, Some(RevealPolicy::TypeWord));
+        let recent = state.recent_spoilers(user, 10);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].id, posted);
+        assert_eq!(recent[0].shared_policy, Some(RevealPolicy::Confirm));
+        match &recent[0].content {
+            Content::String(text) => assert_eq!(text, "Snape kills Dumbledore"),
+            _ => panic!("the kind of content changed"),
+        }
+        assert!(state.recent_spoilers(other, 10).is_empty());
+
+        // claimed spoilers count towards the creator's limit
+        let created = state.create_spoiler(None, text("Hodor"), None, Some(user));
+        state.claim_spoiler(&typed, user, None);
+        let recent: Vec<String> = state
+            .recent_spoilers(user, 10)
+            .into_iter()
+            .map(|spoiler| spoiler.id)
+            .collect();
+        assert_eq!(recent, [typed, created]);
+        assert!(state.get_spoiler(&posted).is_none());
+    }
+
+    #[test]
+    fn copies_of_removed_spoilers_are_deleted() {
+        let (state, _expirations) = state(Config {
+            max_spoilers: 2,
+            ..Config::default()
+        });
+        let copy = |message| Origin {
+            chat: ChatId(-100),
+            message: teloxide::types::MessageId(message),
+        };
+        let text = || Content::Text("Snape kills Dumbledore".to_string());
+
+        let discarded = state.create_spoiler(None, text(), None, None);
+        state.set_spoiler_origin(&discarded, copy(1));
+        let expired = state.create_spoiler(None, text(), None, None);
+        state.set_spoiler_origin(&expired, copy(2));
+        assert!(state.take_stale_copies().is_empty());
+
+        state.discard_spoiler(&discarded);
+        state.remove_spoiler(&sealed::lookup_key(&expired));
+        let evicted = state.create_spoiler(None, text(), None, None);
+        state.set_spoiler_origin(&evicted, copy(3));
+        state.create_spoiler(None, text(), None, None);
+        state.create_spoiler(None, text(), None, None);
+
+        let stale: Vec<i32> = state
+            .take_stale_copies()
+            .iter()
+            .map(|origin| origin.message.0)
+            .collect();
+        assert_eq!(stale, vec![1, 2, 3]);
+        assert!(state.take_stale_copies().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn taps_within_the_double_tap_window_add_up() {
+        let (state, _expirations) = state(Config::default());
+        let user = UserId(42);
+        let window = state.config.double_tap_window;
+
+        assert_eq!(state.remaining_taps(&user, "abc123", 3), 2);
+        tokio::time::advance(window).await;
+        assert_eq!(state.remaining_taps(&user, "abc123", 3), 1);
+        assert_eq!(state.remaining_taps(&UserId(43), "abc123", 3), 2);
+        assert_eq!(state.remaining_taps(&user, "abc124", 3), 2);
+        tokio::time::advance(window).await;
+        assert_eq!(state.remaining_taps(&user, "abc123", 3), 0);
+
+        // counting starts over once the spoiler is revealed
+        assert_eq!(state.remaining_taps(&user, "abc123", 3), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn taps_outside_the_double_tap_window_start_over() {
+        let (state, _expirations) = state(Config::default());
+        let user = UserId(42);
+        let window = state.config.double_tap_window;
+
+        assert_eq!(state.remaining_taps(&user, "abc123", 2), 1);
+        tokio::time::advance(window + Duration::from_millis(1)).await;
+        assert_eq!(state.remaining_taps(&user, "abc123", 2), 1);
+        assert_eq!(state.remaining_taps(&user, "abc123", 2), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn confirmations_need_a_recent_tap() {
+        let (state, _expirations) = state(Config::default());
+        let user = UserId(42);
+        let window = state.config.double_tap_window;
+
+        assert!(!state.confirm_tap(&user, "abc123"));
+
+        state.register_tap(user, "abc123");
+        tokio::time::advance(window).await;
+        assert!(state.confirm_tap(&user, "abc123"));
+        assert!(!state.confirm_tap(&user, "abc123"));
+
+        state.register_tap(user, "abc123");
+        tokio::time::advance(window + Duration::from_millis(1)).await;
+        assert!(!state.confirm_tap(&user, "abc123"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expired_taps_are_removed() {
+        let (state, _expirations) = state(Config::default());
+        let window = state.config.double_tap_window;
+
+        state.register_tap(UserId(42), "abc123");
+        state.remaining_taps(&UserId(43), "abc123", 2);
+        tokio::time::advance(window).await;
+        state.register_tap(UserId(44), "abc123");
+        state.remove_expired_taps();
+        assert_eq!(state.pending_taps.len(), 3);
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        state.remove_expired_taps();
+        assert_eq!(state.pending_taps.len(), 1);
+        assert!(state.confirm_tap(&UserId(44), "abc123"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abandoned_drafts_are_discarded() {
+        let (state, _expirations) = state(Config::default());
+        let timeout = state.config.draft_timeout;
+        let (started, sent_content, proceeding) = (UserId(42), UserId(43), UserId(44));
+
+        state.set_waiting_for_spoiler(started);
+        state.set_waiting_for_spoiler(sent_content);
+        state.new_spoiler(sent_content, Content::Text("spoiler".to_string()));
+        state.set_waiting_for_title(sent_content);
+        state.set_waiting_for_spoiler(proceeding);
+
+        tokio::time::advance(timeout).await;
+        assert!(state.discard_abandoned_drafts().is_empty());
+        state.new_spoiler(proceeding, Content::Text("spoiler".to_string()));
+        state.set_waiting_for_title(proceeding);
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        // only users whose content got discarded are told about it
+        assert_eq!(state.discard_abandoned_drafts(), [sent_content]);
+        assert!(!state.waiting_for_spoiler(&started));
+        assert!(!state.waiting_for_title(&sent_content));
+        assert!(state.waiting_for_title(&proceeding));
+        assert_eq!(state.new_spoilers.len(), 1);
+    }
+
+    #[test]
+    fn finished_drafts_are_not_discarded() {
+        let (state, _expirations) = state(Config {
+            draft_timeout: Duration::ZERO,
+            ..Config::default()
+        });
+        let user = UserId(42);
+
+        state.set_waiting_for_spoiler(user);
+        state.new_spoiler(user, Content::Text("spoiler".to_string()));
+        state.set_waiting_for_title(user);
+        let id = state
+            .set_spoiler_title_and_expiration(user, "title".to_string(), None)
+            .unwrap();
+
+        assert!(!state.waiting_for_title(&user));
+        assert!(state.discard_abandoned_drafts().is_empty());
+        assert_eq!(state.get_spoiler_title(&id).as_deref(), Some("title"));
+    }
+
+    #[test]
+    fn cancelling_discards_the_draft() {
+        let (state, _expirations) = state(Config::default());
+        let user = UserId(42);
+
+        state.set_waiting_for_spoiler(user);
+        state.new_spoiler(user, Content::Text("spoiler".to_string()));
+        state.set_waiting_for_title(user);
+
+        assert_eq!(
+            state.cancel_spoiler_creation(&user),
+            Some(SpoilerCreationStatus::WaitingForTitle)
+        );
+        assert!(!state.waiting_for_title(&user));
+        assert!(state.new_spoilers.is_empty());
+        assert_eq!(state.cancel_spoiler_creation(&user), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_oldest_draft_is_discarded_when_there_are_too_many() {
+        let (state, _expirations) = state(Config {
+            max_drafts: 2,
+            ..Config::default()
+        });
+        let second = Duration::from_secs(1);
+
+        state.new_spoiler(UserId(42), Content::Text("first".to_string()));
+        state.set_waiting_for_title(UserId(42));
+        tokio::time::advance(second).await;
+        state.new_spoiler(UserId(43), Content::Text("second".to_string()));
+        tokio::time::advance(second).await;
+        let evicted = state.new_spoiler(UserId(43), Content::Text("second, edited".to_string()));
+        assert_eq!(evicted, None);
+        assert_eq!(state.new_spoilers.len(), 2);
+
+        let evicted = state.new_spoiler(UserId(44), Content::Text("third".to_string()));
+        assert_eq!(evicted, Some(UserId(42)));
+        assert_eq!(state.new_spoilers.len(), 2);
+        assert!(!state.new_spoilers.contains_key(&UserId(42)));
+        // the user is not asked for a title of the discarded draft anymore
+        assert!(!state.waiting_for_title(&UserId(42)));
+        let title = "title".to_string();
+        assert_eq!(
+            state.set_spoiler_title_and_expiration(UserId(42), title, None),
+            None
+        );
+    }
+
+    #[test]
+    fn concurrent_handlers_keep_the_state_consistent() {
+        let (state, _expirations) = state(Config {
+            max_spoilers: 300,
+            max_spoilers_per_user: 50,
+            ..Config::default()
+        });
+        let created = AtomicU64::new(0);
+        let discarded = AtomicU64::new(0);
+
+        thread::scope(|scope| {
+            for thread in 0..8 {
+                let (state, created, discarded) = (&state, &created, &discarded);
+                scope.spawn(move || {
+                    for i in 0..500u64 {
+                        // some spoilers are created in a private chat, others in groups
+                        let creator = Some(UserId(i % 4)).filter(|_| (thread + i) % 3 != 0);
+                        let text = format!("spoiler {} of thread {}", i, thread);
+                        let id =
+                            state.create_spoiler(None, Content::Text(text.clone()), None, creator);
+                        created.fetch_add(1, Ordering::Relaxed);
+
+                        // others may have evicted it already, but never mixed it up
+                        if let Some(spoiler) = state.get_spoiler(&id) {
+                            match spoiler.content {
+                                Content::Text(stored) => assert_eq!(stored, text),
+                                _ => panic!("the kind of content changed"),
+                            }
+                        }
+                        if i % 5 == 0 && state.get_spoiler(&id).is_some() {
+                            state.discard_spoiler(&id);
+                            if state.get_spoiler(&id).is_none() {
+                                discarded.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                        state.recent_spoilers(UserId(i % 4), 2);
+                    }
+                });
+            }
+        });
+
+        let spoilers = state.spoilers.lock();
+        assert!(spoilers.len() <= state.config.max_spoilers);
+        let mut created_in_private = 0;
+        for user in (0..4).map(UserId) {
+            assert!(spoilers.count_by(&user) <= state.config.max_spoilers_per_user);
+            for lookup_key in spoilers.created_by(&user) {
+                assert_eq!(spoilers.get(lookup_key).unwrap().creator, Some(user));
+                created_in_private += 1;
+            }
+        }
+        assert!(created_in_private <= spoilers.len());
+
+        let evicted = Metrics::get(&state.metrics.evicted_spoilers)
+            + Metrics::get(&state.metrics.evicted_user_spoilers);
+        let removed = discarded.load(Ordering::Relaxed) + evicted;
+        assert!(removed <= created.load(Ordering::Relaxed));
+        assert!(spoilers.len() as u64 >= created.load(Ordering::Relaxed) - removed);
+    }
+
+    #[test]
+    fn concurrent_actions_stay_within_the_rate_limits() {
+        let limit = |capacity| Limit {
+            capacity,
+            period: Duration::from_secs(1_000_000),
+        };
+        let (state, _expirations) = state(Config {
+            rate_limits: RateLimits {
+                spoiler: limit(50),
+                chat: limit(100),
+                ..RateLimits::default()
+            },
+            ..Config::default()
+        });
+        let allowed: Vec<AtomicU64> = (0..4).map(|_| AtomicU64::new(0)).collect();
+
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                let (state, allowed) = (&state, &allowed);
+                scope.spawn(move || {
+                    for i in 0..100 {
+                        let user = i % 4;
+                        let subjects = [Subject::User(UserId(user)), Subject::Chat(ChatId(-100))];
+                        if state.allow(Action::Spoiler, &subjects) {
+                            allowed[user as usize].fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+
+        let allowed: Vec<u64> = allowed.iter().map(Metrics::get).collect();
+        assert!(allowed.iter().all(|&count| count <= 50));
+        // tokens taken from users are given back when the chat is out of them
+        assert_eq!(allowed.iter().sum::<u64>(), 100);
     }
 }
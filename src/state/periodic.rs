@@ -1,38 +1,124 @@
-//! Periodically polls for expired entries.
+//! Periodically removes expired entries.
 //!
-//! Expired spoilers are going to be removed from the state.
+//! Expired spoilers, outdated taps on spoiler buttons, pages of long spoilers users stopped
+//! reading, confirmation words users did not type in time, used tokens that expired, full rate
+//! limiting buckets and abandoned drafts are going to be removed from the state. Copies of
+//! spoiled messages in the forward chat are deleted once their spoiler is gone.
 use std::sync::Arc;
 
-use futures_util::stream::poll_fn;
-use tokio::{
-    stream::StreamExt,
-    time::{delay_for, Duration},
+use teloxide::{prelude::Requester, Bot};
+use tokio::time::{sleep, Duration};
+
+use crate::{
+    state::{expiry::Expirations, State},
+    strings::bot_replies::DRAFT_DISCARDED,
 };
 
-use crate::state::State;
-
-/// Periodically polls for expired entries from the [DelayQueue].
-///
-/// This function periodically checks for entries in the [DelayQueue] that are going to expire next
-/// and removes these instances from the [expirations].
-///
-/// [DelayQueue]: tokio::time::delay_queue::DelayQueue
-/// [expirations]: crate::state::State#structfield.expirations
-pub(crate) async fn poll_for_expired_entries(state: Arc<State>) {
-    // There might be a better way to poll new expirations, but this should be fine for now...
-    #[allow(irrefutable_let_patterns)]
-    while let item = poll_fn(|cx| state.expirations.lock().unwrap().poll_expired(cx))
-        .next()
-        .await
-    {
-        if let Some(Ok(result)) = item {
-            let cache_key = result.into_inner();
-            let _entry;
-            {
-                _entry = state.spoilers.lock().unwrap().remove(&cache_key)
+/// Removes spoilers from the state once they expire.
+///
+/// The [`Expirations`] wake up right when the next spoiler is due, see [expiry].
+///
+/// [`Expirations`]: crate::state::expiry::Expirations
+/// [expiry]: crate::state::expiry
+pub(crate) async fn remove_expired_spoilers(expirations: Expirations, state: Arc<State>) {
+    expirations
+        .run(|lookup_key| state.remove_spoiler(&lookup_key))
+        .await;
+}
+
+/// Periodically removes taps on spoiler buttons that can no longer be followed by another one.
+///
+/// Users who stop tapping before a spoiler is revealed would otherwise stay in [pending_taps]
+/// forever.
+///
+/// [pending_taps]: crate::state::State#structfield.pending_taps
+pub(crate) async fn remove_expired_taps(state: Arc<State>) {
+    loop {
+        sleep(state.config.double_tap_window.max(Duration::from_secs(1))).await;
+        state.remove_expired_taps();
+    }
+}
+
+/// Periodically removes the pages of long spoilers users stopped reading.
+///
+/// See [open_pages].
+///
+/// [open_pages]: crate::state::State#structfield.open_pages
+pub(crate) async fn remove_expired_pages(state: Arc<State>) {
+    loop {
+        sleep(Duration::from_secs(60)).await;
+        state.remove_expired_pages();
+    }
+}
+
+/// Periodically stops waiting for confirmation words users did not type in time.
+///
+/// See [awaiting_confirmation].
+///
+/// [awaiting_confirmation]: crate::state::State#structfield.awaiting_confirmation
+pub(crate) async fn remove_expired_confirmations(state: Arc<State>) {
+    loop {
+        sleep(Duration::from_secs(60)).await;
+        state.remove_expired_confirmations();
+    }
+}
+
+/// Periodically removes used tokens that expired, since they would be rejected anyway.
+///
+/// See [used_tokens].
+///
+/// [used_tokens]: crate::state::State#structfield.used_tokens
+pub(crate) async fn remove_expired_tokens(state: Arc<State>) {
+    loop {
+        sleep(Duration::from_secs(60)).await;
+        state.remove_expired_tokens();
+    }
+}
+
+/// Periodically removes rate limiting buckets that have been refilled completely.
+///
+/// A full bucket behaves the same as one that does not exist yet, see [rate_limits].
+///
+/// [rate_limits]: crate::state::State#structfield.rate_limits
+pub(crate) async fn remove_full_buckets(state: Arc<State>) {
+    loop {
+        sleep(Duration::from_secs(60)).await;
+        state.remove_full_buckets();
+    }
+}
+
+/// Periodically deletes the copies in the [forward chat] of spoilers that expired or were
+/// removed, see [`State::take_stale_copies`].
+///
+/// [forward chat]: crate::config::Config#structfield.forward_chat
+pub(crate) async fn delete_stale_copies(bot: Bot, state: Arc<State>) {
+    loop {
+        sleep(Duration::from_secs(60)).await;
+
+        for origin in state.take_stale_copies() {
+            if let Err(e) = bot.delete_message(origin.chat, origin.message).await {
+                dbg!(e);
+            }
+        }
+    }
+}
+
+/// Periodically cancels the spoiler creation of users who did not proceed for too long.
+///
+/// Unless disabled in the [config], the users are told that their draft was discarded.
+///
+/// [config]: crate::config::Config#structfield.notify_discarded_drafts
+pub(crate) async fn discard_abandoned_drafts(bot: Bot, state: Arc<State>) {
+    loop {
+        sleep(Duration::from_secs(60)).await;
+
+        for user in state.discard_abandoned_drafts() {
+            if !state.config.notify_discarded_drafts {
+                continue;
+            }
+            if let Err(e) = bot.send_message(user, DRAFT_DISCARDED).await {
+                dbg!(e);
             }
-        } else {
-            delay_for(Duration::from_secs(1)).await;
         }
     }
 }
@@ -0,0 +1,165 @@
+//! Token bucket rate limiting of users and chats.
+//!
+//! Each user and each chat gets a bucket per [`Action`]. Every action takes a token from the
+//! bucket, which refills at a steady rate up to its capacity. Once the bucket is empty, the
+//! action is refused until a token has been refilled.
+use std::{fmt, str::FromStr};
+
+use teloxide::types::{ChatId, UserId};
+use tokio::time::{Duration, Instant};
+
+/// Something users do that is rate limited.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub(crate) enum Action {
+    /// Creating a spoiler, via inline query or in a private chat.
+    Create,
+    /// Tapping the button of a spoiler.
+    Reveal,
+    /// Turning a message into a spoiler via `/spoiler`.
+    Spoiler,
+}
+
+/// Who is rate limited.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub(crate) enum Subject {
+    /// A single user.
+    User(UserId),
+    /// All users of a chat together.
+    Chat(ChatId),
+}
+
+/// How many actions may be taken within a period of time.
+///
+/// Parsed from strings like `20/60`, meaning 20 actions per 60 seconds. All of them may be taken
+/// at once, after which the bucket refills evenly over the period.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(crate) struct Limit {
+    /// The number of actions that may be taken at once.
+    pub(crate) capacity: u32,
+    /// The period of time in which an empty bucket refills completely.
+    pub(crate) period: Duration,
+}
+
+impl Limit {
+    /// Creates a limit of `capacity` actions per `seconds` seconds.
+    pub(crate) const fn per_seconds(capacity: u32, seconds: u64) -> Self {
+        Limit {
+            capacity,
+            period: Duration::from_secs(seconds),
+        }
+    }
+}
+
+impl FromStr for Limit {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (capacity, seconds) = value.split_once('/').ok_or(())?;
+        let capacity = capacity.trim().parse().map_err(|_| ())?;
+        let seconds = seconds.trim().parse().map_err(|_| ())?;
+        if capacity == 0 || seconds == 0 {
+            return Err(());
+        }
+
+        Ok(Limit::per_seconds(capacity, seconds))
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.capacity, self.period.as_secs())
+    }
+}
+
+/// The tokens left for one subject and action.
+pub(crate) struct Bucket {
+    /// The number of tokens left, including fractions of tokens refilled so far.
+    tokens: f64,
+    /// When tokens were last refilled.
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    /// Creates a full bucket.
+    pub(super) fn full(limit: Limit) -> Self {
+        Bucket {
+            tokens: f64::from(limit.capacity),
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Refills the bucket and takes a token from it.
+    ///
+    /// Returns `false` if the bucket is empty.
+    pub(super) fn take(&mut self, limit: Limit) -> bool {
+        self.refill(limit);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Puts back a token that was taken, but not used after all.
+    pub(super) fn give_back(&mut self, limit: Limit) {
+        self.tokens = (self.tokens + 1.0).min(f64::from(limit.capacity));
+    }
+
+    /// Refills the bucket and returns `true` if it is full, so it may as well be forgotten.
+    pub(super) fn is_full(&mut self, limit: Limit) -> bool {
+        self.refill(limit);
+        self.tokens >= f64::from(limit.capacity)
+    }
+
+    /// Adds the tokens refilled since the last refill.
+    fn refill(&mut self, limit: Limit) {
+        let now = Instant::now();
+        let capacity = f64::from(limit.capacity);
+        let refilled = now.duration_since(self.refilled_at).as_secs_f64()
+            / limit.period.as_secs_f64()
+            * capacity;
+
+        self.tokens = (self.tokens + refilled).min(capacity);
+        self.refilled_at = now;
+    }
+}
+
+/// The limits of each action.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RateLimits {
+    /// How many spoilers a user may create.
+    pub(crate) create: Limit,
+    /// How often a user may tap spoiler buttons.
+    pub(crate) reveal: Limit,
+    /// How often a user may use `/spoiler`.
+    pub(crate) spoiler: Limit,
+    /// How often all users of a chat together may use `/spoiler`.
+    ///
+    /// Reveals are only limited per user, as every member of a busy group tapping the same
+    /// spoiler would otherwise exhaust the group's limit in no time.
+    pub(crate) chat: Limit,
+}
+
+impl RateLimits {
+    /// Returns the limit of the given action for the given subject.
+    pub(crate) fn limit(&self, action: Action, subject: Subject) -> Limit {
+        match (action, subject) {
+            (_, Subject::Chat(_)) => self.chat,
+            (Action::Create, Subject::User(_)) => self.create,
+            (Action::Reveal, Subject::User(_)) => self.reveal,
+            (Action::Spoiler, Subject::User(_)) => self.spoiler,
+        }
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            create: Limit::per_seconds(60, 60),
+            reveal: Limit::per_seconds(30, 60),
+            spoiler: Limit::per_seconds(20, 60),
+            chat: Limit::per_seconds(120, 60),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Policies deciding how much effort it takes to reveal a spoiler.
+use serde::{Deserialize, Serialize};
+
+use crate::strings::{CONFIRM_SPOILER, SHOW_SPOILER, SHOW_SPOILER_IN_PM};
+
+/// The highest number of taps a [`RevealPolicy::Taps`] may require.
+pub(crate) static MAX_TAPS: u8 = 9;
+
+/// How a spoiler gets revealed.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub(crate) enum RevealPolicy {
+    /// The spoiler is revealed after tapping its button the given number of times in a row.
+    ///
+    /// A single tap makes a *minor spoiler*, more taps make a *major spoiler*.
+    Taps(u8),
+    /// The spoiler is revealed after tapping its button and confirming with a second button.
+    Confirm,
+    /// The spoiler is revealed in a private chat after typing a confirmation word.
+    TypeWord,
+}
+
+impl RevealPolicy {
+    /// The policies users can choose from, in the order they are presented.
+    pub(crate) const CHOICES: [RevealPolicy; 5] = [
+        RevealPolicy::Taps(1),
+        RevealPolicy::Taps(2),
+        RevealPolicy::Taps(3),
+        RevealPolicy::Confirm,
+        RevealPolicy::TypeWord,
+    ];
+
+    /// A short description of this policy.
+    pub(crate) fn description(self) -> String {
+        match self {
+            RevealPolicy::Taps(1) => "single tap".to_string(),
+            RevealPolicy::Taps(2) => "double tap".to_string(),
+            RevealPolicy::Taps(taps) => format!("{} taps", taps),
+            RevealPolicy::Confirm => "tap and confirm".to_string(),
+            RevealPolicy::TypeWord => "type a word in PM".to_string(),
+        }
+    }
+
+    /// The name of spoilers using this policy.
+    pub(crate) fn spoiler_name(self) -> &'static str {
+        match self {
+            RevealPolicy::Taps(1) => "Minor spoiler",
+            RevealPolicy::Taps(_) => "Major spoiler",
+            RevealPolicy::Confirm => "Confirmed spoiler",
+            RevealPolicy::TypeWord => "Hidden spoiler",
+        }
+    }
+
+    /// The labels of the buttons below a spoiler using this policy, one per button.
+    pub(crate) fn button_labels(self) -> Vec<String> {
+        match self {
+            RevealPolicy::Taps(1) => vec![SHOW_SPOILER.to_string()],
+            RevealPolicy::Taps(2) => vec!["Double tap to show spoiler".to_string()],
+            RevealPolicy::Taps(taps) => vec![format!("Tap {} times to show spoiler", taps)],
+            RevealPolicy::Confirm => vec![SHOW_SPOILER.to_string(), CONFIRM_SPOILER.to_string()],
+            RevealPolicy::TypeWord => vec![SHOW_SPOILER_IN_PM.to_string()],
+        }
+    }
+
+    /// Returns the policy that follows this one among the [choices], wrapping around after the
+    /// last one.
+    ///
+    /// [choices]: RevealPolicy::CHOICES
+    pub(crate) fn next(self) -> RevealPolicy {
+        let next = RevealPolicy::CHOICES
+            .iter()
+            .position(|policy| *policy == self)
+            .map_or(0, |index| (index + 1) % RevealPolicy::CHOICES.len());
+        RevealPolicy::CHOICES[next]
+    }
+
+    /// A short code identifying this policy in callback data.
+    pub(crate) fn code(self) -> String {
+        match self {
+            RevealPolicy::Taps(taps) => taps.to_string(),
+            RevealPolicy::Confirm => "c".to_string(),
+            RevealPolicy::TypeWord => "w".to_string(),
+        }
+    }
+
+    /// Returns the policy identified by the given code.
+    pub(crate) fn from_code(code: &str) -> Option<RevealPolicy> {
+        match code {
+            "c" => Some(RevealPolicy::Confirm),
+            "w" => Some(RevealPolicy::TypeWord),
+            _ => match code.parse::<u8>() {
+                Ok(taps) if (1..=MAX_TAPS).contains(&taps) => Some(RevealPolicy::Taps(taps)),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl Default for RevealPolicy {
+    fn default() -> Self {
+        RevealPolicy::Taps(1)
+    }
+}
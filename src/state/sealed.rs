@@ -0,0 +1,189 @@
+//! Encryption of spoiled content at rest.
+//!
+//! Texts and captions of stored spoilers are encrypted with a key derived from the spoiler id.
+//! The id itself is never stored: spoilers are kept under a [`lookup_key`] that is derived from
+//! the id as well, but does not allow to recover it. Whoever wants to read a stored spoiler
+//! therefore needs its id, which only exists in the buttons and links that were handed out.
+//!
+//! The only exception are spoilers with a creator, who may post them again from their recent
+//! spoilers. Their id is [sealed for the creator](seal_id) with a key derived from the bot's
+//! [secret] and the creator's user id. The stored spoilers alone therefore still do not give away
+//! any content, but together with the secret, the spoilers of users who created them in a private
+//! chat or posted them via inline query can be opened. Spoilers created via `/spoiler` have no
+//! creator and stay unreadable without their id.
+//!
+//! [secret]: crate::config::Config#structfield.secret
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use teloxide::types::UserId;
+
+/// Prefixes the spoiler id when deriving the key it is stored under.
+static LOOKUP_KEY_CONTEXT: &[u8] = b"spoilerowobot lookup key:";
+
+/// Prefixes the spoiler id when deriving the key its content is encrypted with.
+static CONTENT_KEY_CONTEXT: &[u8] = b"spoilerowobot content key:";
+
+/// Prefixes the creator when deriving the key the ids of their spoilers are sealed with.
+static CREATOR_KEY_CONTEXT: &str = "spoilerowobot creator key:";
+
+/// The length of the random nonce stored in front of each ciphertext.
+const NONCE_LENGTH: usize = 12;
+
+/// Returns the key a spoiler with the given id is stored under.
+pub(crate) fn lookup_key(spoiler_id: &str) -> String {
+    derive(LOOKUP_KEY_CONTEXT, spoiler_id)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Encrypts the text with the key of the given spoiler.
+///
+/// The result is the base64 encoded nonce followed by the ciphertext.
+pub(crate) fn seal(spoiler_id: &str, text: &str) -> String {
+    encrypt(&cipher(spoiler_id), text)
+}
+
+/// Decrypts text that was [sealed](seal) with the key of the given spoiler.
+///
+/// Returns `None` if the text was sealed for another spoiler or has been tampered with.
+pub(crate) fn open(spoiler_id: &str, sealed: &str) -> Option<String> {
+    decrypt(&cipher(spoiler_id), sealed)
+}
+
+/// Encrypts the id of a spoiler so only the bot can recover it for the spoiler's creator, using
+/// the given secret.
+pub(crate) fn seal_id(secret: &[u8], creator: UserId, spoiler_id: &str) -> String {
+    encrypt(&creator_cipher(secret, creator), spoiler_id)
+}
+
+/// Decrypts a spoiler id that was [sealed](seal_id) for the given creator with the given secret.
+///
+/// Returns `None` if the id was sealed for someone else, with another secret or has been tampered
+/// with.
+pub(crate) fn open_id(secret: &[u8], creator: UserId, sealed: &str) -> Option<String> {
+    decrypt(&creator_cipher(secret, creator), sealed)
+}
+
+/// Encrypts the text with the given cipher.
+///
+/// The result is the base64 encoded nonce followed by the ciphertext.
+fn encrypt(cipher: &ChaCha20Poly1305, text: &str) -> String {
+    let nonce = rand::thread_rng().gen::<[u8; NONCE_LENGTH]>();
+    let mut sealed = nonce.to_vec();
+    sealed.extend(
+        cipher
+            .encrypt(Nonce::from_slice(&nonce), text.as_bytes())
+            .expect("encrypting in memory does not fail"),
+    );
+
+    base64::encode(sealed)
+}
+
+/// Decrypts text that was [encrypted](encrypt) with the given cipher.
+///
+/// Returns `None` if the text was encrypted with another key or has been tampered with.
+fn decrypt(cipher: &ChaCha20Poly1305, sealed: &str) -> Option<String> {
+    let sealed = base64::decode(sealed).ok()?;
+    if sealed.len() < NONCE_LENGTH {
+        return None;
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+    let text = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+
+    String::from_utf8(text).ok()
+}
+
+/// Returns the cipher using the content key of the given spoiler.
+fn cipher(spoiler_id: &str) -> ChaCha20Poly1305 {
+    let key = derive(CONTENT_KEY_CONTEXT, spoiler_id);
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+/// Returns the cipher using the key the ids of the given creator's spoilers are sealed with.
+fn creator_cipher(secret: &[u8], creator: UserId) -> ChaCha20Poly1305 {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(format!("{}{}", CREATOR_KEY_CONTEXT, creator.0).as_bytes());
+    let key = mac.finalize().into_bytes();
+
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+/// Hashes the spoiler id along with the purpose of the derived value.
+fn derive(context: &[u8], spoiler_id: &str) -> Vec<u8> {
+    Sha256::new()
+        .chain_update(context)
+        .chain_update(spoiler_id.as_bytes())
+        .finalize()
+        .to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_with_its_id() {
+        let sealed = seal("abc123", "Snape kills Dumbledore");
+
+        assert_eq!(
+            open("abc123", &sealed).as_deref(),
+            Some("Snape kills Dumbledore")
+        );
+    }
+
+    #[test]
+    fn is_unreadable_without_its_id() {
+        let sealed = seal("abc123", "Snape kills Dumbledore");
+
+        assert!(!sealed.contains("Dumbledore"));
+        assert_eq!(open("abc124", &sealed), None);
+        assert_eq!(open(&lookup_key("abc123"), &sealed), None);
+    }
+
+    #[test]
+    fn rejects_tampered_text() {
+        let mut sealed = base64::decode(seal("abc123", "Snape kills Dumbledore")).unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+
+        assert_eq!(open("abc123", &base64::encode(&sealed)), None);
+        assert_eq!(open("abc123", "not base64!"), None);
+        assert_eq!(open("abc123", &base64::encode([0; NONCE_LENGTH - 1])), None);
+    }
+
+    #[test]
+    fn seals_the_same_text_differently() {
+        assert_ne!(seal("abc123", "spoiler"), seal("abc123", "spoiler"));
+    }
+
+    #[test]
+    fn ids_open_only_for_their_creator_and_secret() {
+        let sealed = seal_id(b"secret", UserId(42), "abc123");
+
+        assert!(!sealed.contains("abc123"));
+        assert_eq!(
+            open_id(b"secret", UserId(42), &sealed).as_deref(),
+            Some("abc123")
+        );
+        assert_eq!(open_id(b"secret", UserId(43), &sealed), None);
+        assert_eq!(open_id(b"other secret", UserId(42), &sealed), None);
+        assert_eq!(open("abc123", &sealed), None);
+    }
+
+    #[test]
+    fn lookup_key_does_not_reveal_the_id() {
+        let key = lookup_key("abc123");
+
+        assert_eq!(key, lookup_key("abc123"));
+        assert_ne!(key, lookup_key("abc124"));
+        assert!(!key.contains("abc123"));
+        assert_eq!(key.len(), 64);
+    }
+}
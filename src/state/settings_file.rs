@@ -0,0 +1,120 @@
+//! Keeps the settings of group chats in a JSON file, so they survive a restart.
+//!
+//! The file maps chat ids to their [`ChatSettings`]. It is read once at startup and written
+//! again after every change. Writes go to a temporary file first, which then replaces the old
+//! one, so a crash never leaves a half written file behind.
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use teloxide::types::ChatId;
+
+use super::chat_settings::ChatSettings;
+
+/// Reads the settings of all chats from the given file.
+///
+/// A file that does not exist yet holds no settings.
+pub(super) fn load(path: &Path) -> io::Result<Vec<(ChatId, ChatSettings)>> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let settings: BTreeMap<i64, ChatSettings> = serde_json::from_str(&json)?;
+
+    Ok(settings
+        .into_iter()
+        .map(|(chat, settings)| (ChatId(chat), settings))
+        .collect())
+}
+
+/// Writes the settings of all chats to the given file, replacing its previous content.
+pub(super) fn save(
+    path: &Path,
+    settings: impl Iterator<Item = (ChatId, ChatSettings)>,
+) -> io::Result<()> {
+    let settings: BTreeMap<i64, ChatSettings> = settings
+        .map(|(chat, settings)| (chat.0, settings))
+        .collect();
+    let json = serde_json::to_string_pretty(&settings)?;
+
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, json)?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::PathBuf};
+
+    use tokio::time::Duration;
+
+    use super::*;
+    use crate::{
+        state::{filter::Filter, reveal::RevealPolicy},
+        util,
+    };
+
+    /// Returns the path of a settings file no other test uses.
+    fn path() -> PathBuf {
+        env::temp_dir().join(format!("spoilerowobot-test-{}.json", util::random_id()))
+    }
+
+    #[test]
+    fn a_missing_file_holds_no_settings() {
+        assert!(load(&path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn settings_are_loaded_as_saved() {
+        let path = path();
+        let settings = ChatSettings {
+            filters: vec![
+                Filter::keyword("Dumbledore", None),
+                Filter::regex(r"ep\d+", Some(Duration::from_secs(60))).unwrap(),
+            ],
+            hashtags: vec!["#spoiler".to_string()],
+            reveal: RevealPolicy::Confirm,
+            show_author: false,
+            ..ChatSettings::default()
+        };
+        save(&path, vec![(ChatId(-100), settings.clone())].into_iter()).unwrap();
+
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.len(), 1);
+        let (chat, loaded) = &loaded[0];
+        assert_eq!(*chat, ChatId(-100));
+        assert_eq!(loaded.hashtags, settings.hashtags);
+        assert_eq!(loaded.reveal, settings.reveal);
+        assert_eq!(loaded.show_author, settings.show_author);
+        assert_eq!(loaded.default_expiration, settings.default_expiration);
+        let filters = |settings: &ChatSettings| -> Vec<String> {
+            settings.filters.iter().map(Filter::to_string).collect()
+        };
+        assert_eq!(filters(loaded), filters(&settings));
+        assert!(loaded.filters[0].matches("Snape kills DUMBLEDORE"));
+        assert!(loaded.filters[1].matches("What happened in EP12?"));
+    }
+
+    #[test]
+    fn missing_settings_take_their_default_value() {
+        let path = path();
+        fs::write(&path, r##"{"-100": {"hashtags": ["#spoiler"]}}"##).unwrap();
+
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let (_, settings) = &loaded[0];
+        assert_eq!(settings.hashtags, ["#spoiler"]);
+        assert_eq!(settings.reveal, ChatSettings::default().reveal);
+        assert!(settings.filters.is_empty());
+    }
+
+    #[test]
+    fn broken_files_are_an_error() {
+        let path = path();
+        fs::write(&path, "{").unwrap();
+
+        let loaded = load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(loaded.is_err());
+    }
+}
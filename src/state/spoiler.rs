@@ -1,16 +1,23 @@
 //! A spoiler to be held in the bot's state.
-use tbot::types::{
-    message::Text, Animation, Audio, Contact, Dice, Document, Location, PhotoSize, Sticker, Video,
-    VideoNote, Voice,
+use teloxide::types::{
+    Animation, Audio, ChatId, Contact, Dice, Document, Location, MediaKind, Message, MessageId,
+    MessageKind, PhotoSize, Seconds, Sticker, UserId, Video, VideoNote, Voice,
 };
-use tokio::time::Duration;
+use tokio::time::{Duration, Instant};
 
-use crate::util::DAY_IN_SECS;
+use crate::{
+    state::{reveal::RevealPolicy, sealed},
+    util::DAY_IN_SECS,
+};
 
 /// Information about a Spoiler.
 #[derive(Clone)]
 pub(crate) struct Spoiler {
-    /// The spoiler id. todo do i need this?
+    /// The spoiler id.
+    ///
+    /// Spoilers held in the state carry their [lookup key] instead, so the id is never stored.
+    ///
+    /// [lookup key]: crate::state::sealed::lookup_key
     pub(crate) id: String,
     /// The title of the Spoiler. Setting a spoiler title is optional.
     pub(crate) title: Option<String>,
@@ -18,6 +25,23 @@ pub(crate) struct Spoiler {
     pub(crate) content: Content,
     /// The amount of time until the spoiler expires.
     pub(crate) expires_in: Duration,
+    /// A forwarded copy of the original message, if the spoiler was created from one.
+    pub(crate) origin: Option<Origin>,
+    /// How the spoiler is revealed, if that was decided when it was posted.
+    ///
+    /// Spoilers created in a private chat or via inline query are offered with several policies,
+    /// so it is up to the [signed](crate::bot::payload) buttons they were posted with.
+    pub(crate) policy: Option<RevealPolicy>,
+    /// The user who created the spoiler in a private chat or posted it via inline query.
+    pub(crate) creator: Option<UserId>,
+    /// The spoiler id [sealed for its creator](sealed::seal_id), so they can post it again from
+    /// their recent spoilers.
+    pub(crate) sealed_id: Option<String>,
+    /// How the creator last posted the spoiler via inline query, so it is posted the same way
+    /// again from their recent spoilers.
+    pub(crate) shared_policy: Option<RevealPolicy>,
+    /// When the spoiler was created.
+    pub(crate) created_at: Instant,
 }
 
 impl Spoiler {
@@ -27,36 +51,204 @@ impl Spoiler {
         title: Option<String>,
         content: Content,
         expires_in: Option<Duration>,
+        creator: Option<UserId>,
     ) -> Self {
         Spoiler {
             id,
             title,
             content,
             expires_in: expires_in.unwrap_or_else(|| Duration::from_secs(DAY_IN_SECS)),
+            origin: None,
+            policy: None,
+            creator,
+            sealed_id: None,
+            shared_policy: None,
+            created_at: Instant::now(),
         }
     }
+
+    /// Returns the time left until the spoiler expires.
+    pub(crate) fn remaining(&self) -> Duration {
+        (self.created_at + self.expires_in).saturating_duration_since(Instant::now())
+    }
+}
+
+/// The location of a message that still carries the spoiled content along with its author.
+#[derive(Clone)]
+pub(crate) struct Origin {
+    /// The chat the message was forwarded to.
+    pub(crate) chat: ChatId,
+    /// The id of the forwarded message within that chat.
+    pub(crate) message: MessageId,
 }
 
 /// An enum holding information about the spoiled content.
+///
+/// Media are stored along with their caption, which is empty if they were sent without one.
 #[non_exhaustive]
 #[derive(Clone)]
 pub(crate) enum Content {
-    Animation(Box<Animation>, Text),
-    Audio(Box<Audio>, Text),
+    Animation(Box<Animation>, String),
+    Audio(Box<Audio>, String),
     Contact(Contact),
     Dice(Dice),
-    Document(Box<Document>, Text),
+    Document(Box<Document>, String),
     Location(Location),
-    Photo(Vec<PhotoSize>, Text, Option<String>),
+    Photo(Vec<PhotoSize>, String, Option<String>),
     Sticker(Box<Sticker>),
 
     /// This one is a workaround for created spoilers from inline queries since we have
     /// no matching Text message available to save.
     String(String),
-    Text(Text),
-    Video(Box<Video>, Text, Option<String>),
+    Text(String),
+    Video(Box<Video>, String, Option<String>),
     VideoNote(VideoNote),
-    Voice(Voice, Text),
+    Voice(Voice, String),
+}
+
+impl Content {
+    /// Maps a message to the content it would be spoiled as.
+    ///
+    /// Returns `None` for kinds of messages that cannot be spoiled.
+    pub(crate) fn from_message(message: &Message) -> Option<Content> {
+        let media = match &message.kind {
+            MessageKind::Common(common) => &common.media_kind,
+            MessageKind::Dice(dice) => return Some(Content::Dice(dice.dice.clone())),
+            _ => return None,
+        };
+        let caption = |caption: &Option<String>| caption.clone().unwrap_or_default();
+
+        match media {
+            MediaKind::Animation(media) => Some(Content::Animation(
+                Box::new(media.animation.clone()),
+                caption(&media.caption),
+            )),
+            MediaKind::Audio(media) => Some(Content::Audio(
+                Box::new(media.audio.clone()),
+                caption(&media.caption),
+            )),
+            MediaKind::Contact(media) => Some(Content::Contact(media.contact.clone())),
+            MediaKind::Document(media) => Some(Content::Document(
+                Box::new(media.document.clone()),
+                caption(&media.caption),
+            )),
+            MediaKind::Location(media) => Some(Content::Location(media.location)),
+            MediaKind::Photo(media) => Some(Content::Photo(
+                media.photo.clone(),
+                caption(&media.caption),
+                media.media_group_id.clone().map(|id| id.0),
+            )),
+            MediaKind::Sticker(media) => Some(Content::Sticker(Box::new(media.sticker.clone()))),
+            MediaKind::Text(media) => Some(Content::Text(media.text.clone())),
+            MediaKind::Video(media) => Some(Content::Video(
+                Box::new(media.video.clone()),
+                caption(&media.caption),
+                media.media_group_id.clone().map(|id| id.0),
+            )),
+            MediaKind::VideoNote(media) => Some(Content::VideoNote(media.video_note.clone())),
+            MediaKind::Voice(media) => {
+                Some(Content::Voice(media.voice.clone(), caption(&media.caption)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Encrypts the text or caption of the content with the key of the given spoiler.
+    pub(super) fn seal(mut self, spoiler_id: &str) -> Content {
+        if let Some(text) = self.text_mut() {
+            *text = sealed::seal(spoiler_id, text);
+        }
+        self
+    }
+
+    /// Decrypts the text or caption of content that was [sealed](Content::seal) with the key of
+    /// the given spoiler.
+    ///
+    /// Returns `None` if the content was sealed for another spoiler.
+    pub(super) fn open(mut self, spoiler_id: &str) -> Option<Content> {
+        if let Some(text) = self.text_mut() {
+            *text = sealed::open(spoiler_id, text)?;
+        }
+        Some(self)
+    }
+
+    /// The name of the kind of content, which also names its [thumbnail].
+    ///
+    /// [thumbnail]: crate::config::Config::thumbnail
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Content::Animation(..) => "animation",
+            Content::Audio(..) => "audio",
+            Content::Contact(_) => "contact",
+            Content::Dice(_) => "dice",
+            Content::Document(..) => "document",
+            Content::Location(_) => "location",
+            Content::Photo(..) => "photo",
+            Content::Sticker(_) => "sticker",
+            Content::String(_) | Content::Text(_) => "text",
+            Content::Video(..) => "video",
+            Content::VideoNote(_) => "video_note",
+            Content::Voice(..) => "voice",
+        }
+    }
+
+    /// Returns `true` if Telegram itself can blur the content, which works for photos, videos and
+    /// GIFs.
+    pub(crate) fn can_be_blurred(&self) -> bool {
+        matches!(
+            self,
+            Content::Animation(..) | Content::Photo(..) | Content::Video(..)
+        )
+    }
+
+    /// Describes the content without giving it away, e.g. `Video, 1:05` or
+    /// `Document report.pdf`.
+    pub(crate) fn summary(&self) -> String {
+        match self {
+            Content::Animation(..) => "GIF".to_string(),
+            Content::Audio(audio, _) => format!("Audio, {}", minutes(audio.duration)),
+            Content::Contact(_) => "Contact".to_string(),
+            Content::Dice(_) => "Dice".to_string(),
+            Content::Document(document, _) => match &document.file_name {
+                Some(file_name) => format!("Document {}", file_name),
+                None => "Document".to_string(),
+            },
+            Content::Location(_) => "Location".to_string(),
+            Content::Photo(..) => "Photo".to_string(),
+            Content::Sticker(_) => "Sticker".to_string(),
+            Content::String(_) | Content::Text(_) => "Text".to_string(),
+            Content::Video(video, ..) => format!("Video, {}", minutes(video.duration)),
+            Content::VideoNote(video_note) => {
+                format!("Video message, {}", minutes(video_note.duration))
+            }
+            Content::Voice(voice, _) => format!("Voice message, {}", minutes(voice.duration)),
+        }
+    }
+
+    /// Returns the text or caption of the content, if it has one.
+    fn text_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Content::Animation(_, caption)
+            | Content::Audio(_, caption)
+            | Content::Document(_, caption)
+            | Content::Photo(_, caption, _)
+            | Content::Text(caption)
+            | Content::Video(_, caption, _)
+            | Content::Voice(_, caption)
+            | Content::String(caption) => Some(caption),
+            Content::Contact(_)
+            | Content::Dice(_)
+            | Content::Location(_)
+            | Content::Sticker(_)
+            | Content::VideoNote(_) => None,
+        }
+    }
+}
+
+/// Formats the duration as minutes and seconds, e.g. `1:05`.
+fn minutes(duration: Seconds) -> String {
+    let seconds = duration.seconds();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
 }
 
 /// Current status of the spoiler creation process.
@@ -69,3 +261,177 @@ pub(crate) enum SpoilerCreationStatus {
     /// The bot is currently waiting for the title of the spoiler from the user.
     WaitingForTitle,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::bot::recorded;
+
+    /// Returns the content a recorded group message with the given fields is spoiled as.
+    fn content(fields: Value) -> Option<Content> {
+        Content::from_message(&recorded::group_message(fields))
+    }
+
+    /// Returns the kind, summary and text of the content.
+    fn describe(mut content: Content) -> (&'static str, String, Option<String>) {
+        let text = content.text_mut().cloned();
+        (content.kind(), content.summary(), text)
+    }
+
+    #[test]
+    fn texts_are_spoiled_as_is() {
+        let text = content(json!({ "text": "Snape kills Dumbledore" })).unwrap();
+
+        assert_eq!(
+            describe(text),
+            (
+                "text",
+                "Text".to_string(),
+                Some("Snape kills Dumbledore".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn media_keep_their_caption() {
+        let photo = content(json!({
+            "media_group_id": "13598431546",
+            "photo": [
+                { "file_id": "AgACAgQAAx0", "file_unique_id": "AQADw7", "width": 90, "height": 67 },
+                { "file_id": "AgACAgQAAx1", "file_unique_id": "AQADw8", "width": 1280, "height": 960 },
+            ],
+            "caption": "Snape kills Dumbledore",
+            "has_media_spoiler": true,
+        }))
+        .unwrap();
+        match &photo {
+            Content::Photo(sizes, _, media_group_id) => {
+                assert_eq!(sizes.len(), 2);
+                assert_eq!(media_group_id.as_deref(), Some("13598431546"));
+            }
+            _ => panic!("not spoiled as a photo"),
+        }
+        assert_eq!(
+            describe(photo),
+            (
+                "photo",
+                "Photo".to_string(),
+                Some("Snape kills Dumbledore".to_string())
+            )
+        );
+
+        let document = content(json!({
+            "document": {
+                "file_id": "BQACAgQAAx0",
+                "file_unique_id": "AgADxA",
+                "file_name": "report.pdf",
+                "mime_type": "application/pdf",
+                "file_size": 48213,
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            describe(document),
+            (
+                "document",
+                "Document report.pdf".to_string(),
+                Some(String::new())
+            )
+        );
+
+        let voice = content(json!({
+            "voice": {
+                "file_id": "AwACAgQAAx0",
+                "file_unique_id": "AgADxQ",
+                "duration": 65,
+                "mime_type": "audio/ogg",
+            },
+            "caption": "listen",
+        }))
+        .unwrap();
+        assert_eq!(
+            describe(voice),
+            (
+                "voice",
+                "Voice message, 1:05".to_string(),
+                Some("listen".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn other_content_is_spoiled_without_text() {
+        let dice = content(json!({ "dice": { "emoji": "🎯", "value": 6 } })).unwrap();
+        match &dice {
+            Content::Dice(dice) => assert_eq!(dice.value, 6),
+            _ => panic!("not spoiled as a dice"),
+        }
+        assert_eq!(describe(dice), ("dice", "Dice".to_string(), None));
+
+        let sticker = content(json!({
+            "sticker": {
+                "file_id": "CAACAgIAAx0",
+                "file_unique_id": "AgADxg",
+                "type": "regular",
+                "width": 512,
+                "height": 512,
+                "is_animated": false,
+                "is_video": false,
+                "emoji": "🦉",
+            },
+        }))
+        .unwrap();
+        assert_eq!(describe(sticker), ("sticker", "Sticker".to_string(), None));
+
+        let video_note = content(json!({
+            "video_note": {
+                "file_id": "DQACAgQAAx0",
+                "file_unique_id": "AgADxw",
+                "length": 240,
+                "duration": 5,
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            describe(video_note),
+            ("video_note", "Video message, 0:05".to_string(), None)
+        );
+
+        let location = content(json!({
+            "location": { "latitude": 52.52, "longitude": 13.405 },
+        }))
+        .unwrap();
+        assert_eq!(
+            describe(location),
+            ("location", "Location".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn service_messages_are_not_spoiled() {
+        let joined = content(json!({
+            "new_chat_members": [{ "id": 10001, "is_bot": false, "first_name": "Ada" }],
+        }));
+
+        assert!(joined.is_none());
+    }
+
+    #[test]
+    fn sealing_keeps_everything_but_the_text() {
+        let photo = content(json!({
+            "photo": [{ "file_id": "AgACAgQAAx0", "file_unique_id": "AQADw7", "width": 90, "height": 67 }],
+            "caption": "Snape kills Dumbledore",
+        }))
+        .unwrap();
+
+        let sealed = photo.clone().seal("abc123");
+        let (kind, summary, caption) = describe(sealed.clone());
+        assert_eq!((kind, summary.as_str()), ("photo", "Photo"));
+        assert_ne!(caption.as_deref(), Some("Snape kills Dumbledore"));
+
+        assert!(sealed.clone().open("abc124").is_none());
+        assert_eq!(describe(sealed.open("abc123").unwrap()), describe(photo));
+    }
+}
@@ -0,0 +1,159 @@
+//! The store of all registered spoilers.
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use teloxide::types::UserId;
+use tokio::time::Instant;
+
+use crate::state::spoiler::Spoiler;
+
+/// All registered spoilers along with an index of who created them and one of when they were
+/// created.
+///
+/// These are kept together so they can be changed at once, e.g. a spoiler never exists in an
+/// index without existing in the map or the other way around.
+#[derive(Default)]
+pub(crate) struct SpoilerStore {
+    /// A key-value store of all currently registered spoilers.
+    ///
+    /// Spoilers are stored under their [lookup key] with their content [sealed], so they can
+    /// only be read by someone who knows their id.
+    ///
+    /// [lookup key]: crate::state::sealed::lookup_key
+    /// [sealed]: crate::state::spoiler::Content::seal
+    spoilers: HashMap<String, Spoiler>,
+
+    /// A key-value store of the spoilers each user created, by their lookup key, in the order
+    /// they were attributed to the user, oldest first.
+    by_user: HashMap<UserId, VecDeque<String>>,
+
+    /// The lookup keys of all spoilers ordered by when they were created, oldest first.
+    by_age: BTreeSet<(Instant, String)>,
+}
+
+impl SpoilerStore {
+    /// Returns the spoiler stored under the given lookup key.
+    pub(super) fn get(&self, lookup_key: &str) -> Option<&Spoiler> {
+        self.spoilers.get(lookup_key)
+    }
+
+    /// Returns the spoiler stored under the given lookup key for changing it.
+    pub(super) fn get_mut(&mut self, lookup_key: &str) -> Option<&mut Spoiler> {
+        self.spoilers.get_mut(lookup_key)
+    }
+
+    /// Returns the number of stored spoilers.
+    pub(super) fn len(&self) -> usize {
+        self.spoilers.len()
+    }
+
+    /// Returns the number of spoilers stored that were created by the given user.
+    pub(super) fn count_by(&self, creator: &UserId) -> usize {
+        self.by_user.get(creator).map_or(0, VecDeque::len)
+    }
+
+    /// Returns the lookup key of the spoiler that was created first.
+    pub(super) fn oldest(&self) -> Option<String> {
+        self.by_age
+            .first()
+            .map(|(_, lookup_key)| lookup_key.clone())
+    }
+
+    /// Returns the lookup key of the spoiler the given user created first.
+    pub(super) fn oldest_by(&self, creator: &UserId) -> Option<String> {
+        self.by_user.get(creator)?.front().cloned()
+    }
+
+    /// Returns the lookup keys of the spoilers the given user created, newest first.
+    pub(super) fn created_by(&self, creator: &UserId) -> impl Iterator<Item = &String> {
+        self.by_user
+            .get(creator)
+            .into_iter()
+            .flat_map(|created| created.iter().rev())
+    }
+
+    /// Stores the spoiler under its id, which is expected to be its lookup key and not taken yet.
+    pub(super) fn insert(&mut self, spoiler: Spoiler) {
+        if let Some(creator) = spoiler.creator {
+            self.by_user
+                .entry(creator)
+                .or_default()
+                .push_back(spoiler.id.clone());
+        }
+        self.by_age.insert((spoiler.created_at, spoiler.id.clone()));
+        self.spoilers.insert(spoiler.id.clone(), spoiler);
+    }
+
+    /// Attributes the spoiler stored under the given lookup key to the given creator, if it has
+    /// none yet.
+    ///
+    /// Returns `false` if there is no such spoiler or it already has a creator.
+    pub(super) fn attribute(&mut self, lookup_key: &str, creator: UserId) -> bool {
+        match self.spoilers.get_mut(lookup_key) {
+            Some(spoiler) if spoiler.creator.is_none() => spoiler.creator = Some(creator),
+            _ => return false,
+        }
+        self.by_user
+            .entry(creator)
+            .or_default()
+            .push_back(lookup_key.to_string());
+
+        true
+    }
+
+    /// Removes the spoiler stored under the given lookup key.
+    pub(super) fn remove(&mut self, lookup_key: &str) -> Option<Spoiler> {
+        let spoiler = self.spoilers.remove(lookup_key)?;
+        self.by_age
+            .remove(&(spoiler.created_at, spoiler.id.clone()));
+        self.forget_creator(&spoiler);
+
+        Some(spoiler)
+    }
+
+    /// Removes the spoiler from the spoilers of its creator.
+    fn forget_creator(&mut self, spoiler: &Spoiler) {
+        if let Some(creator) = spoiler.creator {
+            if let Some(created) = self.by_user.get_mut(&creator) {
+                created.retain(|key| key != &spoiler.id);
+                if created.is_empty() {
+                    self.by_user.remove(&creator);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::spoiler::Content;
+
+    /// Creates a spoiler stored under the given lookup key.
+    fn spoiler(lookup_key: &str, creator: Option<UserId>) -> Spoiler {
+        let content = Content::Text("Snape kills Dumbledore".to_string());
+        Spoiler::new(lookup_key.to_string(), None, content, None, creator)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_oldest_spoilers_are_found_in_creation_order() {
+        let mut store = SpoilerStore::default();
+        let creator = UserId(42);
+        for (lookup_key, creator) in [("a", None), ("b", Some(creator)), ("c", Some(creator))] {
+            store.insert(spoiler(lookup_key, creator));
+            tokio::time::advance(tokio::time::Duration::from_secs(1)).await;
+        }
+        assert_eq!(store.oldest().as_deref(), Some("a"));
+        assert_eq!(store.oldest_by(&creator).as_deref(), Some("b"));
+
+        store.remove("a");
+        assert_eq!(store.oldest().as_deref(), Some("b"));
+        store.remove("c");
+        assert_eq!(store.oldest().as_deref(), Some("b"));
+        assert_eq!(store.count_by(&creator), 1);
+
+        store.remove("b");
+        assert_eq!(store.oldest(), None);
+        assert_eq!(store.oldest_by(&creator), None);
+        assert!(store.by_age.is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+//! Signed tokens that grant access to a spoiler.
+//!
+//! Spoiler ids are never handed out on their own. Deep links and shared inline queries carry a
+//! token instead, consisting of the spoiler id, the time it was issued and an HMAC over both,
+//! the user it was issued to and its [`TokenPurpose`]. The HMAC is keyed with the bot's
+//! [secret], so tokens cannot be forged, and a token issued to one user is useless to anyone else.
+//!
+//! [secret]: crate::config::Config#structfield.secret
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use teloxide::types::UserId;
+
+use crate::util::ID_LENGTH;
+
+/// The amount of time a token stays valid if it is [single use](TokenPurpose::single_use).
+pub(crate) static TOKEN_LIFETIME_SECS: i64 = 10 * 60;
+
+/// The length of the hex encoded time a token was issued at.
+const ISSUED_LENGTH: usize = 8;
+
+/// The number of bytes of the HMAC kept in a token.
+const SIGNATURE_BYTES: usize = 16;
+
+/// The length of the base64url encoded signature of a token.
+const SIGNATURE_LENGTH: usize = 22;
+
+/// What a token may be used for.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(crate) enum TokenPurpose {
+    /// Revealing a spoiler in a private chat via deep link.
+    Reveal,
+    /// Being asked for the confirmation word of a spoiler via deep link.
+    TypeWord,
+    /// Sharing a spoiler via inline query.
+    Share,
+}
+
+impl TokenPurpose {
+    /// Separates tokens of different purposes, so one cannot be used in place of another.
+    fn tag(self) -> &'static str {
+        match self {
+            TokenPurpose::Reveal => "reveal",
+            TokenPurpose::TypeWord => "word",
+            TokenPurpose::Share => "share",
+        }
+    }
+
+    /// Returns `true` if tokens of this purpose expire after [`TOKEN_LIFETIME_SECS`] and may only
+    /// be used once.
+    pub(crate) fn single_use(self) -> bool {
+        match self {
+            TokenPurpose::Reveal | TokenPurpose::TypeWord => true,
+            TokenPurpose::Share => false,
+        }
+    }
+}
+
+/// Why a token was rejected.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(crate) enum TokenError {
+    /// The token was not issued by the bot, or not to this user.
+    Forged,
+    /// The token is older than [`TOKEN_LIFETIME_SECS`].
+    Expired,
+    /// The token has been used before.
+    Reused,
+}
+
+/// A token whose signature has been checked.
+pub(crate) struct Token {
+    /// The id of the spoiler the token grants access to.
+    pub(crate) spoiler_id: String,
+    /// When the token was issued, as a unix timestamp.
+    pub(crate) issued: i64,
+}
+
+/// Issues a token for the given spoiler and user.
+pub(super) fn issue(
+    secret: &[u8],
+    purpose: TokenPurpose,
+    spoiler_id: &str,
+    user: UserId,
+) -> String {
+    let issued = format!("{:08x}", Utc::now().timestamp() as u32);
+    let signature = sign(secret, purpose, spoiler_id, &issued, user);
+
+    format!("{}{}{}", spoiler_id, issued, signature)
+}
+
+/// Checks the signature of a token presented by the given user.
+///
+/// Single use tokens are also checked for their age, but not whether they have been used before.
+pub(super) fn verify(
+    secret: &[u8],
+    purpose: TokenPurpose,
+    token: &str,
+    user: UserId,
+) -> Result<Token, TokenError> {
+    if !token.is_ascii() || token.len() != ID_LENGTH + ISSUED_LENGTH + SIGNATURE_LENGTH {
+        return Err(TokenError::Forged);
+    }
+    let (spoiler_id, rest) = token.split_at(ID_LENGTH);
+    let (issued, signature) = rest.split_at(ISSUED_LENGTH);
+
+    let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| TokenError::Forged)?;
+    mac(secret, purpose, spoiler_id, issued, user)
+        .verify_truncated_left(&signature)
+        .map_err(|_| TokenError::Forged)?;
+
+    let issued = i64::from(u32::from_str_radix(issued, 16).map_err(|_| TokenError::Forged)?);
+    if purpose.single_use() && Utc::now().timestamp() - issued > TOKEN_LIFETIME_SECS {
+        return Err(TokenError::Expired);
+    }
+
+    Ok(Token {
+        spoiler_id: spoiler_id.to_string(),
+        issued,
+    })
+}
+
+/// Returns the base64url encoded signature of a token.
+fn sign(
+    secret: &[u8],
+    purpose: TokenPurpose,
+    spoiler_id: &str,
+    issued: &str,
+    user: UserId,
+) -> String {
+    let signature = mac(secret, purpose, spoiler_id, issued, user)
+        .finalize()
+        .into_bytes();
+
+    base64::encode_config(&signature[..SIGNATURE_BYTES], base64::URL_SAFE_NO_PAD)
+}
+
+/// Returns the HMAC over all parts of a token.
+fn mac(
+    secret: &[u8],
+    purpose: TokenPurpose,
+    spoiler_id: &str,
+    issued: &str,
+    user: UserId,
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(format!("{}:{}:{}:{}", purpose.tag(), spoiler_id, issued, user.0).as_bytes());
+    mac
+}
@@ -1,5 +1,19 @@
 //! A module containing all possible replies the bot could send.
-use super::SPOILER_TITLE_SEPARATOR;
+use teloxide::utils::html;
+
+use crate::{
+    bot::{
+        callback::{alert_pages, MAX_ALERT_LENGTH, MAX_ALERT_PAGES},
+        query::QueryError,
+    },
+    state::{
+        chat_settings::{ChatSettings, ModerationPolicy, AUTHOR_PLACEHOLDER},
+        filter::Filter,
+        metrics::Metrics,
+    },
+};
+
+use super::{CONFIRMATION_WORD, SPOILER_TITLE_SEPARATOR};
 
 /// Informs the user to send the content to be spoiled.
 pub(crate) static PREPARING_A_SPOILER: &'static str =
@@ -14,6 +28,10 @@ pub(crate) static SPOILER_CREATION_CANCELLED: &'static str =
 /// Informs the user that the spoiler is now ready.
 pub(crate) static SPOILER_READY: &'static str = "Done! Your advanced spoiler is ready.";
 
+/// Informs the user that a blurred copy of their spoiler is on its way.
+pub(crate) static BLURRED_COPY_SENT: &'static str =
+    "Here is a blurred copy, forward it wherever you like.";
+
 /// Informs the user that the spoiler could not be found.
 pub(crate) static SPOILER_NOT_FOUND: &'static str =
     "Spoiler not found! It might have expired already...";
@@ -31,6 +49,57 @@ Type a dash (-) now if you do not want a title for your spoiler.";
 /// Informs the user to tap again to show the spoiler
 pub(crate) static TAP_AGAIN_TO_SHOW_SPOILER: &'static str = "Please tap again to see the spoiler";
 
+/// Informs the user to confirm to show the spoiler
+pub(crate) static CONFIRM_TO_SHOW_SPOILER: &'static str =
+    "Tap \"Yes, spoil me\" if you really want to see the spoiler";
+
+/// Informs the user to tap the spoiler button before confirming
+pub(crate) static SHOW_SPOILER_FIRST: &'static str = "Please tap \"Show spoiler\" first";
+
+/// Informs the user that they typed the wrong confirmation word
+pub(crate) static WRONG_CONFIRMATION_WORD: &'static str =
+    "That's not the word, so the spoiler stays hidden. Tap its button again to retry.";
+
+/// Informs the user that the tapped button was created by an older version of the bot
+pub(crate) static OUTDATED_BUTTON: &'static str =
+    "This button is from an older version of the bot and no longer works, sorry!";
+
+/// Informs the user that the tapped button could not be understood
+pub(crate) static UNKNOWN_BUTTON: &'static str = "Sorry, I don't know what this button does.";
+
+/// Informs the user that the link they followed was not issued to them
+pub(crate) static INVALID_LINK: &'static str =
+    "This link was not made for you. Tap the spoiler's button to get your own.";
+
+/// Informs the user that the link they followed has expired
+pub(crate) static EXPIRED_LINK: &'static str =
+    "This link has expired. Tap the spoiler's button again to get a new one.";
+
+/// Informs the user that the link they followed has been used already
+pub(crate) static REUSED_LINK: &'static str =
+    "This link has been used already. Tap the spoiler's button again to get a new one.";
+
+/// Asks the user to wait a moment after hitting a rate limit
+pub(crate) static SLOW_DOWN: &'static str =
+    "Whoa, slow down! Please wait a moment before trying again.";
+
+/// Informs the user that only the bot's operator may see its statistics
+pub(crate) static ONLY_OPERATOR_CAN_SEE_STATS: &'static str =
+    "Sorry, only the operator of this bot can see its statistics.";
+
+/// Informs the user that their spoiler creation was cancelled since they did not proceed
+pub(crate) static DRAFT_DISCARDED: &'static str =
+    "Your draft was discarded since you didn't finish it in time. Send /start to create a new spoiler.";
+
+/// Informs the user that their spoiler creation was cancelled since too many spoilers are being
+/// created at the moment
+pub(crate) static DRAFT_EVICTED: &'static str =
+    "Your draft was discarded since too many spoilers are being created right now. Send /start to create a new spoiler.";
+
+/// Informs the user that their draft is gone, so the title they sent cannot be used
+pub(crate) static DRAFT_NOT_FOUND: &'static str =
+    "Sorry, your draft is gone. Send /start to create a new spoiler.";
+
 /// Informs the user that the bot is not an admin in that chat
 pub(crate) static NOT_AN_ADMIN: &'static str =
     "I need to be a group admin with permission to delete messages in order to do it!";
@@ -39,6 +108,234 @@ pub(crate) static NOT_AN_ADMIN: &'static str =
 pub(crate) static NO_DELETE_PERMISSION: &'static str =
     "I am an admin but I don't have the permission to delete messages from others!";
 
+/// Informs the user that the bot failed to look up the group admins.
+pub(crate) static COULD_NOT_FETCH_ADMINS: &'static str =
+    "I could not look up the admins of this group, please try again later.";
+
+/// Informs the user that the group settings forbid them to spoiler that message.
+pub(crate) static NOT_ALLOWED_TO_SPOILER: &'static str =
+    "The settings of this group don't allow you to spoiler that message.";
+
+/// Informs the user that only admins may change the group settings.
+pub(crate) static ONLY_ADMINS_CAN_CHANGE_SETTINGS: &'static str =
+    "Only group admins can change my settings.";
+
+/// Informs the user that the settings have been saved.
+pub(crate) static SETTINGS_SAVED: &'static str = "Settings saved.";
+
+/// Informs the user that the title template has been changed.
+pub(crate) static TITLE_TEMPLATE_CHANGED: &'static str =
+    "Done! The title template has been changed.";
+
+/// Describes the spoiler settings of a group, shown above the settings menu.
+pub(crate) fn spoiler_settings_text(settings: &ChatSettings) -> String {
+    format!(
+        "Spoiler settings of this group
+Tap the buttons below to change them. Only admins can do so.
+
+Currently, {}.
+Title template: {}
+
+Admins can change the title template by typing
+/spoilersettings title your template
+where {} is replaced by the author's name.",
+        settings.moderation.description(),
+        settings.title_template,
+        AUTHOR_PLACEHOLDER
+    )
+}
+
+/// Informs the user that the spoiler settings have been changed.
+pub(crate) fn spoiler_settings_changed(policy: ModerationPolicy) -> String {
+    format!("Done! From now on, {}.", policy.description())
+}
+
+/// Explains how to manage the spoiler filters of a group.
+pub(crate) static FILTER_USAGE: &'static str =
+    "Messages matching a filter of this group are automatically turned into spoilers.
+
+/spoilerfilter list - show all filters
+/spoilerfilter add keyword /7d - spoiler messages containing the keyword
+/spoilerfilter regex pattern /7d - spoiler messages matching the regular expression
+/spoilerfilter remove 1 - remove the first filter
+/spoilerfilter test message - check whether a message would be spoiled
+
+The expiration suffix (e.g. /7d) is optional and limits how long a filter applies.
+Only admins can add or remove filters.";
+
+/// Informs the user that the filter has been added.
+pub(crate) static FILTER_ADDED: &'static str = "Done! The filter has been added.";
+
+/// Informs the user that there is no such filter.
+pub(crate) static FILTER_NOT_FOUND: &'static str =
+    "There is no such filter. Type /spoilerfilter list to see all filters.";
+
+/// Informs the user that the sample message would be spoiled.
+pub(crate) static FILTER_WOULD_MATCH: &'static str =
+    "That message matches a filter and would be turned into a spoiler.";
+
+/// Informs the user that the sample message would not be spoiled.
+pub(crate) static FILTER_WOULD_NOT_MATCH: &'static str = "That message does not match any filter.";
+
+/// The reason shown for messages that got automatically spoiled by a filter.
+pub(crate) static FILTER_REASON: &'static str = "matches a spoiler filter of this group";
+
+/// Lists the spoiler filters of a group.
+pub(crate) fn filter_list(filters: &[Filter]) -> String {
+    if filters.is_empty() {
+        return "This group has no spoiler filters yet.".to_string();
+    }
+
+    filters
+        .iter()
+        .enumerate()
+        .map(|(index, filter)| format!("{}. {}", index + 1, filter))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Informs the user that the filter has been removed.
+pub(crate) fn filter_removed(filter: &Filter) -> String {
+    format!("Done! The filter {} has been removed.", filter)
+}
+
+/// Informs the user that the regular expression is invalid.
+pub(crate) fn invalid_regex(error: regex::Error) -> String {
+    format!("That is not a valid regular expression:\n{}", error)
+}
+
+/// Describes how an inline query was understood: how the content is revealed given its length,
+/// when the spoiler expires and its title.
+///
+/// Texts that fit into a single alert show how much room is left, longer ones how many
+/// [alerts](alert_pages) they are split into, up to [`MAX_ALERT_PAGES`]. Anything longer is
+/// revealed in a private chat.
+pub(crate) fn query_feedback(content: &str, expires_at: &str, title: Option<&str>) -> String {
+    let length = content.chars().count();
+    let pages = alert_pages(content).len();
+    let length = if pages <= 1 {
+        format!("{}/{} characters", length, MAX_ALERT_LENGTH)
+    } else if pages <= MAX_ALERT_PAGES {
+        format!("{} characters, revealed in {} alerts", length, pages)
+    } else {
+        format!("{} characters, too long for alerts, revealed in PM", length)
+    };
+    let title = match title {
+        Some(title) => format!("titled \"{}\"", title),
+        None => "no title".to_string(),
+    };
+
+    format!("{} · expires at {} · {}", length, expires_at, title)
+}
+
+/// Shown instead of inline results when the query cannot be understood, explaining how to fix it.
+pub(crate) fn invalid_query(error: &QueryError) -> String {
+    // the text of the button it is shown on may only be up to 64 characters long
+    let shorten = |text: &str| text.chars().take(12).collect::<String>();
+
+    match error {
+        QueryError::EmptyContent => "Fix your query: type the spoiler after the title".to_string(),
+        QueryError::InvalidExpiry(expiry) => {
+            format!(
+                "Fix your query: /{} is no expiry, try /30m",
+                shorten(expiry)
+            )
+        }
+        QueryError::UnknownOption(option) => format!(
+            "Fix your query: unknown option [{}], try [minor]",
+            shorten(option)
+        ),
+    }
+}
+
+/// Explains how to manage the spoiler hashtags of a group.
+pub(crate) static HASHTAG_USAGE: &'static str =
+    "Messages starting with a spoiler hashtag of this group are automatically turned into spoilers, \
+using the hashtag as title.
+
+/spoilerhashtags list - show all hashtags
+/spoilerhashtags add #spoiler - turn messages starting with #spoiler into spoilers
+/spoilerhashtags remove #spoiler - stop doing so
+
+Only admins can add or remove hashtags.";
+
+/// Informs the user that the hashtag has been added.
+pub(crate) static HASHTAG_ADDED: &'static str = "Done! The hashtag has been added.";
+
+/// Informs the user that the hashtag has already been added before.
+pub(crate) static HASHTAG_EXISTS: &'static str = "This group already uses that hashtag.";
+
+/// Informs the user that the hashtag has been removed.
+pub(crate) static HASHTAG_REMOVED: &'static str = "Done! The hashtag has been removed.";
+
+/// Informs the user that there is no such hashtag.
+pub(crate) static HASHTAG_NOT_FOUND: &'static str = "This group does not use that hashtag.";
+
+/// Lists the spoiler hashtags of a group.
+pub(crate) fn hashtag_list(hashtags: &[String]) -> String {
+    if hashtags.is_empty() {
+        "This group has no spoiler hashtags yet.".to_string()
+    } else {
+        format!("Spoiler hashtags of this group: {}", hashtags.join(" "))
+    }
+}
+
+/// Tells the author of a message that got spoiled via hashtag how to create spoilers themselves.
+///
+/// The text is formatted as HTML, with the author given as an HTML [mention].
+///
+/// [mention]: teloxide::utils::html::user_mention
+pub(crate) fn hashtag_tip(mention: &str, hashtag: &str, bot_username: &str) -> String {
+    let hashtag = html::escape(hashtag);
+    format!(
+        "{}, I turned your message into a spoiler. Next time, you can do it yourself:
+Type @{} {}{}your spoiler, or reply to your message with /spoiler {}",
+        mention,
+        html::escape(bot_username),
+        hashtag,
+        html::escape(SPOILER_TITLE_SEPARATOR),
+        hashtag
+    )
+}
+
+/// Informs the user how many more taps it takes to show the spoiler
+pub(crate) fn tap_more_times(remaining: u8) -> String {
+    format!("Please tap {} more times to see the spoiler", remaining)
+}
+
+/// Asks the user to type the confirmation word in order to see the spoiler
+pub(crate) fn type_to_show_spoiler(title: &str) -> String {
+    format!(
+        "You are about to see the spoiler \"{}\".
+Type \"{}\" to reveal it, anything else keeps it hidden.",
+        title, CONFIRMATION_WORD
+    )
+}
+
+/// Lists the bot's statistics
+pub(crate) fn stats_text(metrics: &Metrics, spoilers: usize) -> String {
+    format!(
+        "Spoilers stored: {}
+
+Refused due to rate limiting:
+- creating spoilers: {}
+- revealing spoilers: {}
+- /spoiler: {}
+
+Removed to save memory:
+- spoilers, due to the total limit: {}
+- spoilers, due to the limit per user: {}
+- unfinished spoilers: {}",
+        spoilers,
+        Metrics::get(&metrics.rate_limited_creations),
+        Metrics::get(&metrics.rate_limited_reveals),
+        Metrics::get(&metrics.rate_limited_spoilers),
+        Metrics::get(&metrics.evicted_spoilers),
+        Metrics::get(&metrics.evicted_user_spoilers),
+        Metrics::get(&metrics.evicted_drafts),
+    )
+}
+
 /// Sends information how to use this bot.
 pub(crate) fn help_text(bot_username: String) -> String {
     format!(
@@ -49,7 +346,25 @@ You can type quick spoilers by using @{} in inline mode:
 
 Custom titles can also be used from inline mode as follows:
 @{} title for the spoiler{}contents of the spoiler
-Note that the title will be immediately visible!",
-        bot_username, bot_username, bot_username, SPOILER_TITLE_SEPARATOR
+Note that the title will be immediately visible!
+
+In groups, reply to a message with /spoiler to turn it into a spoiler:
+/spoiler title for the spoiler{}reason shown to the group /2d
+Title, reason and expiration are optional.",
+        bot_username, bot_username, bot_username, SPOILER_TITLE_SEPARATOR, SPOILER_TITLE_SEPARATOR
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_feedback_tells_how_the_text_is_revealed() {
+        let feedback = |text: &str| query_feedback(text, "noon", None);
+
+        assert!(feedback("short").starts_with("5/200 characters ·"));
+        assert!(feedback(&"word ".repeat(100)).starts_with("500 characters, revealed in 3 alerts"));
+        assert!(feedback(&"word ".repeat(400)).contains("revealed in PM"));
+    }
+}
@@ -7,17 +7,105 @@ pub(crate) static INLINE_QUERY_SEPARATOR: &'static str = "id-_-";
 /// Distinguish the spoiler title from the spoiler content.
 pub(crate) static SPOILER_TITLE_SEPARATOR: &'static str = ":::";
 
-/// Marks a spoiler as *major Spoiler*.
-pub(crate) static MAJOR_SPOILER_IDENTIFIER: &'static str = "maj_";
+/// The version tag every callback payload starts with.
+///
+/// Bump it whenever the encoding of [`CallbackPayload`] changes, so buttons sent by an older
+/// version of the bot are recognized as outdated.
+///
+/// [`CallbackPayload`]: crate::bot::payload::CallbackPayload
+pub(crate) static CALLBACK_PAYLOAD_VERSION: &'static str = "v2";
+
+/// Separates the fields of a callback payload.
+pub(crate) static CALLBACK_PAYLOAD_SEPARATOR: char = ':';
+
+/// Marks the start parameter of spoilers that are revealed after typing a confirmation word.
+pub(crate) static WORD_CONFIRMATION_PREFIX: &'static str = "word-_-";
+
+/// The word users need to type in order to reveal a spoiler that asks for it.
+pub(crate) static CONFIRMATION_WORD: &'static str = "spoil";
 
 /// Sent whenever a user switches from inline mode to a PM with the bot.
 pub(crate) static CREATE_CUSTOM_SPOILER: &'static str = "create_custom_spoiler";
 
+/// The environment variable holding the path of the file chat settings are kept in.
+pub(crate) static SETTINGS_FILE_VARIABLE: &'static str = "SPOILEROWO_SETTINGS_FILE";
+
+/// The environment variable holding the id of the chat spoiled messages are forwarded to.
+pub(crate) static FORWARD_CHAT_VARIABLE: &'static str = "SPOILEROWO_FORWARD_CHAT";
+
+/// The environment variable holding the time frame for double taps on major spoilers in seconds.
+pub(crate) static DOUBLE_TAP_WINDOW_VARIABLE: &'static str = "SPOILEROWO_DOUBLE_TAP_WINDOW";
+
+/// The environment variable holding the key tokens are signed with.
+pub(crate) static SECRET_VARIABLE: &'static str = "SPOILEROWO_SECRET";
+
+/// The environment variable holding how many spoilers a user may create.
+pub(crate) static CREATE_LIMIT_VARIABLE: &'static str = "SPOILEROWO_CREATE_LIMIT";
+
+/// The environment variable holding how often a user may tap spoiler buttons.
+pub(crate) static REVEAL_LIMIT_VARIABLE: &'static str = "SPOILEROWO_REVEAL_LIMIT";
+
+/// The environment variable holding how often a user may use `/spoiler`.
+pub(crate) static SPOILER_LIMIT_VARIABLE: &'static str = "SPOILEROWO_SPOILER_LIMIT";
+
+/// The environment variable holding how often all users of a chat together may use `/spoiler`.
+pub(crate) static CHAT_LIMIT_VARIABLE: &'static str = "SPOILEROWO_CHAT_LIMIT";
+
+/// The environment variable holding the user id of the bot's operator.
+pub(crate) static OPERATOR_VARIABLE: &'static str = "SPOILEROWO_OPERATOR";
+
+/// The environment variable holding the maximum number of spoilers kept at once.
+pub(crate) static MAX_SPOILERS_VARIABLE: &'static str = "SPOILEROWO_MAX_SPOILERS";
+
+/// The environment variable holding the maximum number of spoilers per user.
+pub(crate) static MAX_SPOILERS_PER_USER_VARIABLE: &'static str = "SPOILEROWO_MAX_SPOILERS_PER_USER";
+
+/// The environment variable holding the maximum number of unfinished spoilers kept at once.
+pub(crate) static MAX_DRAFTS_VARIABLE: &'static str = "SPOILEROWO_MAX_DRAFTS";
+
+/// The environment variable holding the time in seconds after which abandoned drafts are discarded.
+pub(crate) static DRAFT_TIMEOUT_VARIABLE: &'static str = "SPOILEROWO_DRAFT_TIMEOUT";
+
+/// The environment variable holding whether users are told about their discarded drafts.
+pub(crate) static NOTIFY_DISCARDED_DRAFTS_VARIABLE: &'static str =
+    "SPOILEROWO_NOTIFY_DISCARDED_DRAFTS";
+
+/// The environment variable holding the URL inline result thumbnails are served from.
+pub(crate) static THUMBNAIL_URL_VARIABLE: &'static str = "SPOILEROWO_THUMBNAIL_URL";
+
 /// Indicates the content could not be found.
 pub(crate) static ERROR_NO_CONTENT: &'static str = "No content?!?!?!";
 
 /// Instructs the user to send it.
 pub(crate) static SEND_IT: &'static str = "Send it";
 
+/// Asks for a copy of a media spoiler that is blurred by Telegram itself.
+pub(crate) static SEND_BLURRED: &'static str = "Send blurred";
+
+/// Shows the next page of a spoiler that is too long for one alert
+pub(crate) static NEXT_PAGE: &'static str = "Next page";
+
 /// Show spoiler
 pub(crate) static SHOW_SPOILER: &'static str = "Show spoiler";
+
+/// The title of the inline result using Telegram's own spoiler formatting.
+pub(crate) static NATIVE_SPOILER: &'static str = "Native spoiler";
+
+/// Describes the inline result using Telegram's own spoiler formatting.
+pub(crate) static NATIVE_SPOILER_DESCRIPTION: &'static str =
+    "Text, hidden by Telegram itself, readable without the bot";
+
+/// Offers to create an advanced spoiler in a private chat
+pub(crate) static ADVANCED_SPOILER: &'static str = "Advanced spoiler (media etc.)…";
+
+/// The title of a recent spoiler that has no title of its own
+pub(crate) static UNTITLED_SPOILER: &'static str = "Untitled spoiler";
+
+/// Shown instead of inline results after hitting a rate limit
+pub(crate) static SLOW_DOWN_INLINE: &'static str = "Slow down! Try again in a moment…";
+
+/// Confirm to show a spoiler
+pub(crate) static CONFIRM_SPOILER: &'static str = "Yes, spoil me";
+
+/// Show spoiler in a private chat
+pub(crate) static SHOW_SPOILER_IN_PM: &'static str = "Show spoiler in PM";
@@ -1,11 +1,16 @@
 //! Various utility functions needed throughout the project
 extern crate regex;
 
-use std::{sync::Arc, time::Duration};
+use std::time::Duration;
 
 use chrono::Utc;
 use rand::{distributions::Alphanumeric, Rng};
-use tbot::contexts::fields::Context;
+use teloxide::{
+    prelude::*,
+    sugar::request::RequestReplyExt,
+    types::{ChatMember, ChatMemberKind},
+    RequestError,
+};
 
 use crate::strings::INLINE_QUERY_SEPARATOR;
 
@@ -33,11 +38,26 @@ pub(crate) static MONTH_IN_SECS: u64 = 30 * DAY_IN_SECS;
 /// A year is assumed to equal 365 days.
 pub(crate) static YEAR_IN_SECS: u64 = 365 * DAY_IN_SECS;
 
+/// The longest duration [`parse_duration`] accepts, in seconds.
+///
+/// Far longer durations would not fit into the points in time they are added to.
+pub(crate) static MAX_DURATION_IN_SECS: u64 = 100 * YEAR_IN_SECS;
+
+/// The length of identifiers generated by [`random_id`].
+///
+/// 12 alphanumeric characters allow for more than 3 * 10^21 ids, while leaving enough room for
+/// prefixes in callback data and start parameters, which are limited to 64 characters.
+pub(crate) static ID_LENGTH: usize = 12;
+
 /// Generates a random string that acts as an identifier, i.e. a spoiler id.
+///
+/// The id consists of [`ID_LENGTH`] alphanumeric characters, so it may be used in deep links,
+/// callback data and inline queries alike. Whoever stores it is responsible for checking it is
+/// not in use already.
 pub(crate) fn random_id() -> String {
     rand::thread_rng()
         .sample_iter(Alphanumeric)
-        .take(48)
+        .take(ID_LENGTH)
         .map(char::from)
         .collect::<String>()
 }
@@ -50,20 +70,41 @@ pub(crate) fn is_spoiler_id(query: &String) -> bool {
 /// Generates a [Telegram start URL][tg docs] pointing to the bot with the provided start parameter.
 ///
 /// [tg docs]: https://core.telegram.org/bots#deep-linking
-pub(crate) async fn start_url(context: Arc<impl Context>, start_param: &String) -> String {
-    format!(
-        "https://t.me/{}?start={}",
-        context
-            .bot()
-            .get_me()
-            .call()
-            .await
-            .unwrap()
-            .user
-            .username
-            .unwrap(),
-        start_param
-    )
+pub(crate) fn start_url(bot_username: &str, start_param: &String) -> String {
+    format!("https://t.me/{}?start={}", bot_username, start_param)
+}
+
+/// Returns `true` if the user is among the given chat admins.
+pub(crate) fn is_admin(admins: &[ChatMember], user: UserId) -> bool {
+    admins.iter().any(|member| {
+        member.user.id == user
+            && matches!(
+                member.kind,
+                ChatMemberKind::Owner(_) | ChatMemberKind::Administrator(_)
+            )
+    })
+}
+
+/// Returns `true` if the message was sent by an admin of the chat it was sent in.
+pub(crate) async fn sent_by_admin(bot: &Bot, message: &Message) -> Result<bool, RequestError> {
+    let user_id = match message.from.as_ref() {
+        Some(user) => user.id,
+        None => return Ok(false),
+    };
+
+    let admins = bot.get_chat_administrators(message.chat.id).await?;
+    Ok(is_admin(&admins, user_id))
+}
+
+/// Replies to the message with the given text.
+pub(crate) async fn reply(bot: &Bot, message: &Message, text: &str) {
+    if let Err(e) = bot
+        .send_message(message.chat.id, text)
+        .reply_to(message.id)
+        .await
+    {
+        dbg!(e);
+    }
 }
 
 /// Returns a Duration according to the user
@@ -80,6 +121,8 @@ pub(crate) async fn start_url(context: Arc<impl Context>, start_param: &String)
 /// | `M`      | Month. A Month is assumed to be 30 days long. |
 /// | `y`, `Y` | Year. A year is assumed to be 365 days long.  |
 ///
+/// Durations longer than [`MAX_DURATION_IN_SECS`] are rejected.
+///
 /// # Examples
 /// - `/7m` would result into a Duration of 7 minutes,
 /// - `/6M` would result into a Duration of 6 months,
@@ -92,20 +135,24 @@ pub(crate) fn parse_duration(text: &String) -> Option<Duration> {
     if RE.is_match(text) {
         let captures = RE.captures(text).unwrap();
         let amount = captures.get(1).map_or("", |m| m.as_str());
-        let amount = amount.parse::<u64>().unwrap();
+        let amount = amount.parse::<u64>().ok()?;
 
         let unit = captures.get(2).map_or("", |m| m.as_str());
 
-        match unit {
-            "m" => Some(Duration::from_secs(amount * MINUTE_IN_SECS)),
-            "M" => Some(Duration::from_secs(amount * MONTH_IN_SECS)),
-            "s" | "S" => Some(Duration::from_secs(amount)),
-            "h" | "H" => Some(Duration::from_secs(amount * HOUR_IN_SECS)),
-            "d" | "D" => Some(Duration::from_secs(amount * DAY_IN_SECS)),
-            "w" | "W" => Some(Duration::from_secs(amount * WEEK_IN_SECS)),
-            "y" | "Y" => Some(Duration::from_secs(amount * YEAR_IN_SECS)),
-            _ => None,
-        }
+        let unit_in_secs = match unit {
+            "m" => MINUTE_IN_SECS,
+            "M" => MONTH_IN_SECS,
+            "s" | "S" => 1,
+            "h" | "H" => HOUR_IN_SECS,
+            "d" | "D" => DAY_IN_SECS,
+            "w" | "W" => WEEK_IN_SECS,
+            "y" | "Y" => YEAR_IN_SECS,
+            _ => return None,
+        };
+        amount
+            .checked_mul(unit_in_secs)
+            .filter(|secs| *secs <= MAX_DURATION_IN_SECS)
+            .map(Duration::from_secs)
     } else {
         None
     }